@@ -1,323 +1,1258 @@
 use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
+use std::time::Instant;
+use crate::channels::{ChannelMixer, ChannelOptions};
+use crate::devices::{self, DeviceLossPolicy, StreamErrorKind};
+use crate::levels::LevelMeter;
+use crate::loopback::{self, DeviceMatch, DeviceRule};
+#[cfg(target_os = "linux")]
+use crate::pulse;
+use crate::recorder::{self, RecordingOptions, RecordingTarget};
+use crate::resample::{self, ResampleQuality, Resampler};
+use crate::spool::{self, PendingRead, SpooledAudio, SpooledChannels};
+use crate::transcript::SourceTag;
+use crate::vad::{self, VoiceActivityDetector};
 
-// Global static to hold the audio data
-pub static AUDIO_DATA: once_cell::sync::Lazy<Arc<Mutex<Vec<f32>>>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(Vec::new())));
+// Identifies a capture session in commands and events
+pub type SessionId = u64;
 
-// Global static to hold the recording state
-pub static IS_RECORDING: once_cell::sync::Lazy<Arc<AtomicBool>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(AtomicBool::new(false)));
+// How often a session waiting for its device to come back looks for it
+const RECONNECT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
-// Thread handle for the recording thread
-pub static RECORDING_THREAD: once_cell::sync::Lazy<Arc<Mutex<Option<thread::JoinHandle<()>>>>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(None)));
+// Audio the capture callback can queue before the recording thread has to take it
+const QUEUE_SECS: usize = 2;
 
-// Global static to hold the sample rate
-pub static SAMPLE_RATE: once_cell::sync::Lazy<Arc<Mutex<u32>>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(16000)));
+// How long the recording thread waits for a control message before taking in queued audio
+const WORKER_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
 
-// Global static to hold the selected device name
-pub static SELECTED_DEVICE: once_cell::sync::Lazy<Arc<Mutex<Option<String>>>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(None)));
+// Audio read back from the spool at a time when a finished recording is prepared for Whisper
+const READ_CHUNK_SECS: usize = 10;
 
-// Global static to track if we're using system audio
-pub static IS_SYSTEM_AUDIO: once_cell::sync::Lazy<Arc<AtomicBool>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(AtomicBool::new(false)));
+// Longest stretch of a finished recording that is transcribed as one piece, so a long
+// session is never held in memory all at once
+const FINAL_CHUNK_SECS: usize = 600;
 
-// Set whether we're using system audio
-pub fn set_audio_source(is_system: bool) {
-    IS_SYSTEM_AUDIO.store(is_system, Ordering::SeqCst);
+// Stream format negotiated with the device, reported to the frontend when a capture starts
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureFormat {
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // cpal's name for the device's native sample type, e.g. "i16" or "f32"
+    pub sample_format: String,
 }
 
-pub fn start_capture() -> Result<(), String> {
-    // If already recording, return early
-    if IS_RECORDING.load(Ordering::SeqCst) {
-        return Err("Already recording".to_string());
+// Lifecycle of a capture session: open -> recording <-> paused -> stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureState {
+    Open,
+    Recording,
+    Paused,
+    Stopped,
+}
+
+// Where a session captures from
+#[derive(Debug, Clone)]
+pub enum CaptureSource {
+    // An input device by id, or the default input if None
+    Device(Option<String>),
+    // The system's output. On Linux this is the monitor of the named output (the
+    // default output if None); elsewhere the virtual loopback device found by `rules`.
+    System {
+        monitor: Option<String>,
+        rules: Vec<DeviceRule>,
+    },
+}
+
+// A pause in a session. Nothing is captured while paused, so the gap only
+// shows in the timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PausedPeriod {
+    // Where the gap falls in the captured audio, in seconds; unlike event times this
+    // leaves out earlier pauses
+    pub at: f32,
+    // How long the session was paused, in seconds; None while it still is
+    pub duration: Option<f32>,
+    // Position of the gap in the capture buffer
+    #[serde(skip)]
+    pub offset: usize,
+}
+
+// Summary of a session returned by the capture commands
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureSessionInfo {
+    pub id: SessionId,
+    pub state: CaptureState,
+    pub source: Option<SourceTag>,
+    // Set once the stream has started
+    pub format: Option<CaptureFormat>,
+    // Frames dropped because the recording thread fell behind, and how often it happened
+    pub dropped_frames: u64,
+    pub overruns: u64,
+    pub pauses: Vec<PausedPeriod>,
+}
+
+// Counts audio the capture callback had to drop because its queue was full.
+// Updated from the audio thread, so only atomics.
+#[derive(Debug, Default)]
+pub struct OverrunCounters {
+    dropped_frames: AtomicU64,
+    overruns: AtomicU64,
+}
+
+impl OverrunCounters {
+    fn record(&self, frames: u64) {
+        self.dropped_frames.fetch_add(frames, Ordering::Relaxed);
+        self.overruns.fetch_add(1, Ordering::Relaxed);
     }
+    
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+    
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
+// Messages to a session's recording thread
+enum Control {
+    // Answered with the capture offset the pause falls at, once the audio queued before it is taken in
+    Pause(Sender<usize>),
+    // Carries how long the pause lasted, in seconds
+    Resume(f32),
+    Stop,
+    // Reported by the stream's error callback
+    StreamError(cpal::StreamError),
+}
+
+// Audio captured by a session, shared with its capture callback. Both parts are
+// created once the stream has started and are spooled to disk as they grow.
+#[derive(Default)]
+struct CaptureBuffer {
+    // Mono samples at the device sample rate
+    audio: Mutex<Option<SpooledAudio>>,
+    // Every device channel, kept when `keep_channels` is set
+    channels: Mutex<Option<SpooledChannels>>,
+    format: Mutex<Option<CaptureFormat>>,
+}
+
+// One capture stream and the audio it has recorded. The stream runs on its own
+// thread between `start` and `stop`.
+pub struct CaptureSession {
+    id: SessionId,
+    source: CaptureSource,
+    // Set when the session is one side of a dual capture
+    tag: Option<SourceTag>,
+    channel_options: ChannelOptions,
+    device_loss: DeviceLossPolicy,
+    // Where the session's audio is spooled
+    spool_dir: PathBuf,
+    // Where its audio is saved once it stops, if recordings are on
+    recording: Option<RecordingTarget>,
+    state: Mutex<CaptureState>,
+    buffer: Arc<CaptureBuffer>,
+    // Pauses so far, with when the current one began
+    pauses: Mutex<Vec<PausedPeriod>>,
+    paused_since: Mutex<Option<Instant>>,
+    // Set while the recording thread is running; it clears this when it finishes
+    running: Arc<AtomicBool>,
+    overruns: Arc<OverrunCounters>,
+    // Sends pause, resume and stop to the recording thread
+    control: Mutex<Option<Sender<Control>>>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
 
-    // Clear previous audio data
-    AUDIO_DATA.lock().unwrap().clear();
+impl CaptureSession {
+    fn new(
+        id: SessionId,
+        source: CaptureSource,
+        tag: Option<SourceTag>,
+        channel_options: ChannelOptions,
+        device_loss: DeviceLossPolicy,
+        spool_dir: PathBuf,
+        recording: Option<RecordingTarget>,
+    ) -> Self {
+        CaptureSession {
+            id,
+            source,
+            tag,
+            channel_options,
+            device_loss,
+            spool_dir,
+            recording,
+            state: Mutex::new(CaptureState::Open),
+            buffer: Arc::new(CaptureBuffer::default()),
+            pauses: Mutex::new(Vec::new()),
+            paused_since: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+            overruns: Arc::new(OverrunCounters::default()),
+            control: Mutex::new(None),
+            thread: Mutex::new(None),
+        }
+    }
+    
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+    
+    pub fn tag(&self) -> Option<SourceTag> {
+        self.tag
+    }
+    
+    pub fn recording(&self) -> Option<&RecordingTarget> {
+        self.recording.as_ref()
+    }
+    
+    pub fn info(&self) -> CaptureSessionInfo {
+        CaptureSessionInfo {
+            id: self.id,
+            state: *self.state.lock().unwrap(),
+            source: self.tag,
+            format: self.buffer.format.lock().unwrap().clone(),
+            dropped_frames: self.overruns.dropped_frames(),
+            overruns: self.overruns.overruns(),
+            pauses: self.pauses(),
+        }
+    }
     
-    // Set recording flag
-    IS_RECORDING.store(true, Ordering::SeqCst);
+    // Whether the stream is running, paused or not
+    pub fn is_active(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
     
-    // Create a thread to handle the recording
-    let audio_data = AUDIO_DATA.clone();
-    let is_recording = IS_RECORDING.clone();
-    let sample_rate = SAMPLE_RATE.clone();
-    let selected_device = SELECTED_DEVICE.clone();
+    pub fn is_paused(&self) -> bool {
+        *self.state.lock().unwrap() == CaptureState::Paused
+    }
+    
+    pub fn pauses(&self) -> Vec<PausedPeriod> {
+        self.pauses.lock().unwrap().clone()
+    }
     
-    let handle = thread::spawn(move || {
-        let host = match cpal::default_host() {
-            host => host,
+    // Open the device and start recording. Waits until the stream is running and
+    // returns the negotiated format, or the error that stopped it from opening.
+    pub fn start(&self) -> Result<CaptureFormat, String> {
+        let mut state = self.state.lock().unwrap();
+        if *state != CaptureState::Open {
+            return Err("Capture session has already been started".to_string());
+        }
+        
+        self.running.store(true, Ordering::SeqCst);
+        
+        let (control, control_receiver) = mpsc::channel();
+        let stream = CaptureStream {
+            session_id: self.id,
+            channel_options: self.channel_options,
+            device_loss: self.device_loss,
+            tag: self.tag,
+            spool_dir: self.spool_dir.clone(),
+            buffer: self.buffer.clone(),
+            capturing: Arc::new(AtomicBool::new(true)),
+            running: self.running.clone(),
+            overruns: self.overruns.clone(),
+            control: control_receiver,
+            errors: control.clone(),
         };
+        let source = self.source.clone();
+        let (ready, ready_receiver) = mpsc::channel();
         
-        // Get the selected device or default
-        let device = if let Some(device_name) = selected_device.lock().unwrap().as_ref() {
-            // Try to find the device by name
-            let mut found_device = None;
-            
-            if let Ok(devices) = host.input_devices() {
-                for device in devices {
-                    if let Ok(name) = device.name() {
-                        if name == *device_name {
-                            found_device = Some(device);
-                            break;
-                        }
+        let handle = thread::spawn(move || {
+            // Record an output's monitor directly when PulseAudio or PipeWire is running
+            #[cfg(target_os = "linux")]
+            if let CaptureSource::System { monitor, .. } = &source {
+                match pulse::find_monitor_source(monitor.as_deref()) {
+                    Ok(monitor) => {
+                        stream.run_monitor(monitor, ready);
+                        return;
                     }
+                    Err(e) => eprintln!("{}, looking for a loopback device instead", e),
                 }
             }
             
-            match found_device {
-                Some(device) => device,
-                None => {
-                    eprintln!("Selected device '{}' not found, using default", device_name);
-                    match host.default_input_device() {
-                        Some(device) => device,
-                        None => {
-                            eprintln!("No input device available");
-                            is_recording.store(false, Ordering::SeqCst);
-                            return;
-                        }
-                    }
-                }
-            }
-        } else {
-            // Use default device
-            match host.default_input_device() {
-                Some(device) => device,
-                None => {
-                    eprintln!("No input device available");
-                    is_recording.store(false, Ordering::SeqCst);
-                    return;
-                }
+            match find_device(&source) {
+                Ok((device, device_id)) => stream.run(device, device_id, ready),
+                Err(e) => stream.fail(&ready, e),
             }
-        };
+        });
         
-        println!("Using input device: {:?}", device.name().unwrap_or_default());
+        // Wait for the recording thread to open its stream
+        let result = match ready_receiver.recv() {
+            Ok(result) => result,
+            Err(_) => Err("The recording thread stopped before the stream started".to_string()),
+        };
         
-        let config = match device.default_input_config() {
-            Ok(config) => config,
+        match result {
+            Ok(format) => {
+                *self.buffer.format.lock().unwrap() = Some(format.clone());
+                *self.thread.lock().unwrap() = Some(handle);
+                *self.control.lock().unwrap() = Some(control);
+                *state = CaptureState::Recording;
+                Ok(format)
+            }
             Err(e) => {
-                eprintln!("Error getting default config: {}", e);
-                is_recording.store(false, Ordering::SeqCst);
-                return;
+                self.running.store(false, Ordering::SeqCst);
+                let _ = handle.join();
+                *state = CaptureState::Stopped;
+                Err(e)
             }
-        };
+        }
+    }
+    
+    // Stop taking in audio but keep the stream open. Speech in progress is ended
+    // at the pause, so no utterance runs across the gap.
+    pub fn pause(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if *state != CaptureState::Recording {
+            return Err("Not recording".to_string());
+        }
+        
+        let (reply, offset) = mpsc::channel();
+        self.send(Control::Pause(reply));
         
-        // Store the original sample rate
-        *sample_rate.lock().unwrap() = config.sample_rate().0;
+        // A recording thread that has already finished has taken in everything
+        let offset = offset.recv().unwrap_or_else(|_| self.captured_len());
+        self.pauses.lock().unwrap().push(PausedPeriod {
+            at: offset as f32 / self.sample_rate() as f32,
+            duration: None,
+            offset,
+        });
+        *self.paused_since.lock().unwrap() = Some(Instant::now());
         
-        println!("Original sample rate: {}", config.sample_rate().0);
+        *state = CaptureState::Paused;
+        Ok(())
+    }
+    
+    pub fn resume(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if *state != CaptureState::Paused {
+            return Err("Not paused".to_string());
+        }
         
-        // Clone is_recording for the data callback
-        let is_recording_data = is_recording.clone();
-        // Clone is_recording for the error callback
-        let is_recording_error = is_recording.clone();
+        let gap = self.end_pause();
+        self.send(Control::Resume(gap));
+        *state = CaptureState::Recording;
+        Ok(())
+    }
+    
+    // Close the stream. The captured audio stays available until the session is closed.
+    pub fn stop(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, CaptureState::Recording | CaptureState::Paused) {
+            return Err("Not recording".to_string());
+        }
         
-        let stream = match device.build_input_stream(
-            &config.into(),
-            move |data: &[f32], _| {
-                if is_recording_data.load(Ordering::SeqCst) {
-                    audio_data.lock().unwrap().extend_from_slice(data);
-                }
-            },
-            move |err| {
-                eprintln!("Stream error: {}", err);
-                is_recording_error.store(false, Ordering::SeqCst);
-            },
-            None,
-        ) {
-            Ok(stream) => stream,
-            Err(e) => {
-                eprintln!("Error building stream: {}", e);
-                is_recording.store(false, Ordering::SeqCst);
-                return;
-            }
-        };
+        // The recording thread takes in what is still queued before it finishes
+        self.send(Control::Stop);
+        self.end_pause();
+        self.control.lock().unwrap().take();
         
-        if let Err(e) = stream.play() {
-            eprintln!("Error playing stream: {}", e);
-            is_recording.store(false, Ordering::SeqCst);
-            return;
+        // Wait for the recording thread to finish
+        if let Some(handle) = self.thread.lock().unwrap().take() {
+            // Ignore any errors from joining the thread
+            let _ = handle.join();
         }
         
-        // Keep the stream alive as long as we're recording
-        while is_recording.load(Ordering::SeqCst) {
-            thread::sleep(std::time::Duration::from_millis(100));
+        // The spool is complete; it is removed when the session is closed
+        if let Some(audio) = self.buffer.audio.lock().unwrap().as_mut() {
+            audio.finish();
+        }
+        if let Some(channels) = self.buffer.channels.lock().unwrap().as_mut() {
+            channels.finish();
         }
         
-        // Stream will be dropped when this thread ends
-    });
+        *state = CaptureState::Stopped;
+        Ok(())
+    }
     
-    // Store the thread handle
-    *RECORDING_THREAD.lock().unwrap() = Some(handle);
+    // Record how long the current pause lasted, in seconds
+    fn end_pause(&self) -> f32 {
+        let Some(since) = self.paused_since.lock().unwrap().take() else {
+            return 0.0;
+        };
+        let duration = since.elapsed().as_secs_f32();
+        if let Some(pause) = self.pauses.lock().unwrap().last_mut() {
+            pause.duration = Some(duration);
+        }
+        duration
+    }
     
-    Ok(())
-}
-
-// Function to start capturing system audio
-pub fn start_system_capture() -> Result<(), String> {
-    // If already recording, return early
-    if IS_RECORDING.load(Ordering::SeqCst) {
-        return Err("Already recording".to_string());
+    // A recording thread that has already finished has nothing left to control
+    fn send(&self, message: Control) {
+        if let Some(control) = self.control.lock().unwrap().as_ref() {
+            let _ = control.send(message);
+        }
     }
-
-    // Clear previous audio data
-    AUDIO_DATA.lock().unwrap().clear();
     
-    // Set recording flag
-    IS_RECORDING.store(true, Ordering::SeqCst);
+    // Copy the captured samples from `offset` onwards, up to `until` if given, along with
+    // the device sample rate. Used by the streaming transcriber to read audio without
+    // interrupting the capture.
+    pub fn samples_since(&self, offset: usize, until: Option<usize>) -> (Vec<f32>, u32) {
+        let pending = self.buffer.audio.lock().unwrap().as_mut().map(|audio| {
+            let end = until.map_or(audio.len(), |until| until.min(audio.len()));
+            audio.read(offset, end)
+        });
+        
+        // Audio older than the live window is read from disk with the lock released, so
+        // the recording thread can keep taking in audio meanwhile
+        let samples = pending.map(PendingRead::complete).unwrap_or_default();
+        (samples, self.sample_rate())
+    }
     
-    // Create a thread to handle the recording
-    let audio_data = AUDIO_DATA.clone();
-    let is_recording = IS_RECORDING.clone();
-    let sample_rate = SAMPLE_RATE.clone();
+    // Mono samples captured so far
+    pub fn captured_len(&self) -> usize {
+        self.buffer.audio.lock().unwrap().as_ref().map_or(0, |audio| audio.len())
+    }
     
-    let handle = thread::spawn(move || {
-        let host = match cpal::default_host() {
-            host => host,
-        };
+    // Resample the captured samples between two offsets to 16kHz and trim silence.
+    // Used for the final recording and for segments committed while still capturing.
+    // Also returns where the trimmed audio starts in the capture, in seconds.
+    pub fn process_samples(&self, from: usize, to: Option<usize>) -> (Vec<f32>, f32) {
+        // Get a copy of the audio data; anything older than the live window is read from
+        // the spool once the lock is released
+        let pending = self.buffer.audio.lock().unwrap().as_mut().map(|audio| {
+            let end = to.unwrap_or(audio.len());
+            audio.read(from, end)
+        });
+        let audio_data = pending.map(PendingRead::complete).unwrap_or_default();
         
-        // Look for virtual audio devices (like BlackHole, VB-Audio, etc.)
-        let mut virtual_device = None;
-        let virtual_device_names = [
-            "BlackHole", "VB-Audio", "CABLE Output", "Soundflower", "Virtual Audio", "Loopback"
-        ];
-        
-        if let Ok(devices) = host.input_devices() {
-            for device in devices {
-                if let Ok(name) = device.name() {
-                    // Check if the device name contains any of our virtual device keywords
-                    if virtual_device_names.iter().any(|&vname| name.contains(vname)) {
-                        println!("Found virtual audio device: {}", name);
-                        virtual_device = Some(device);
-                        break;
-                    }
-                }
+        let sample_rate = self.sample_rate();
+        let (samples, trimmed) = prepare_for_whisper_trimmed(audio_data, sample_rate);
+        (samples, from as f32 / sample_rate as f32 + trimmed as f32 / 16000.0)
+    }
+    
+    // Prepare the captured samples from `from` onwards for Whisper, reading them back a
+    // piece at a time. `on_chunk` is given up to FINAL_CHUNK_SECS of 16kHz audio at a time,
+    // with where it starts in the capture in seconds and whether it is the last piece.
+    pub fn process_chunks(
+        &self,
+        from: usize,
+        on_chunk: impl FnMut(Vec<f32>, f32, bool) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let read = |start: usize, end: usize| {
+            let pending = self.buffer.audio.lock().unwrap().as_mut().map(|audio| audio.read(start, end));
+            Ok(pending.map(PendingRead::complete).unwrap_or_default())
+        };
+        prepare_in_chunks(from, self.captured_len(), self.sample_rate(), read, on_chunk)
+    }
+    
+    // As `process_chunks`, for one channel of the capture.
+    // Only available when the session kept per-channel buffers.
+    pub fn channel_chunks(
+        &self,
+        channel: usize,
+        on_chunk: impl FnMut(Vec<f32>, f32, bool) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let frames = {
+            let data = self.buffer.channels.lock().unwrap();
+            let data = data
+                .as_ref()
+                .ok_or_else(|| "Separate channels were not kept for this recording".to_string())?;
+            if channel >= data.channels() {
+                return Err(format!("The recording has no channel {}", channel + 1));
             }
-        }
+            data.len()
+        };
         
-        // Use the virtual device or fall back to default
-        let device = match virtual_device {
-            Some(device) => device,
-            None => {
-                eprintln!("No virtual audio device found, using default input device");
-                match host.default_input_device() {
-                    Some(device) => device,
-                    None => {
-                        eprintln!("No input device available");
-                        is_recording.store(false, Ordering::SeqCst);
-                        return;
-                    }
-                }
+        let read = |start: usize, end: usize| match self.buffer.channels.lock().unwrap().as_mut() {
+            Some(data) => data.read_channel(channel, start, end),
+            None => Ok(Vec::new()),
+        };
+        prepare_in_chunks(0, frames, self.sample_rate(), read, on_chunk)
+    }
+    
+    fn sample_rate(&self) -> u32 {
+        self.buffer.format.lock().unwrap().as_ref().map(|format| format.sample_rate).unwrap_or(16000)
+    }
+}
+
+// Every capture session, along with the input preferences new sessions are opened with.
+// Held in Tauri managed state.
+pub struct CaptureSessions {
+    sessions: Mutex<HashMap<SessionId, Arc<CaptureSession>>>,
+    next_id: AtomicU64,
+    selected_device: Mutex<Option<String>>,
+    system_audio: AtomicBool,
+    // Output whose monitor system audio is captured from on Linux
+    selected_monitor: Mutex<Option<String>>,
+    // Rules for finding the loopback device, highest priority first
+    loopback_rules: Mutex<Vec<DeviceRule>>,
+    device_loss: Mutex<DeviceLossPolicy>,
+    channel_options: Mutex<ChannelOptions>,
+    spool_dir: PathBuf,
+    recordings_dir: PathBuf,
+    recording_options: Mutex<RecordingOptions>,
+}
+
+impl CaptureSessions {
+    pub fn new(
+        spool_dir: PathBuf,
+        recordings_dir: PathBuf,
+        loopback_rules: Vec<DeviceRule>,
+        device_loss: DeviceLossPolicy,
+        recording_options: RecordingOptions,
+    ) -> Self {
+        CaptureSessions {
+            sessions: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            selected_device: Mutex::new(None),
+            system_audio: AtomicBool::new(false),
+            selected_monitor: Mutex::new(None),
+            loopback_rules: Mutex::new(loopback_rules),
+            device_loss: Mutex::new(device_loss),
+            channel_options: Mutex::new(ChannelOptions::default()),
+            spool_dir,
+            recordings_dir,
+            recording_options: Mutex::new(recording_options),
+        }
+    }
+    
+    // Open a session on the selected device, or on system audio if `system` is set.
+    // `tag` marks the session as one side of a call when both sides are captured.
+    pub fn open(&self, system: bool, tag: Option<SourceTag>) -> Arc<CaptureSession> {
+        let source = if system {
+            CaptureSource::System {
+                monitor: self.selected_monitor.lock().unwrap().clone(),
+                rules: self.loopback_rules(),
             }
+        } else {
+            CaptureSource::Device(self.selected_device.lock().unwrap().clone())
         };
         
-        println!("Using system audio device: {:?}", device.name().unwrap_or_default());
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let recording = recorder::target(&self.recordings_dir, &self.recording_options(), id, tag);
+        let session = Arc::new(CaptureSession::new(
+            id,
+            source,
+            tag,
+            self.channel_options(),
+            self.device_loss(),
+            self.spool_dir.clone(),
+            recording,
+        ));
+        
+        self.sessions.lock().unwrap().insert(id, session.clone());
+        session
+    }
+    
+    // Open a session on the current audio source
+    pub fn open_default(&self) -> Arc<CaptureSession> {
+        self.open(self.system_audio.load(Ordering::SeqCst), None)
+    }
+    
+    pub fn get(&self, id: SessionId) -> Result<Arc<CaptureSession>, String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("No capture session with id {}", id))
+    }
+    
+    pub fn list(&self) -> Vec<CaptureSessionInfo> {
+        let mut sessions: Vec<CaptureSessionInfo> = self.sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|session| session.info())
+            .collect();
+        sessions.sort_by_key(|session| session.id);
+        sessions
+    }
+    
+    // Stop the session if it is still running and forget it
+    pub fn close(&self, id: SessionId) -> Result<(), String> {
+        let session = self.sessions
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or_else(|| format!("No capture session with id {}", id))?;
         
+        // Going by the state rather than `is_active` also stops sessions whose recording
+        // thread ended by itself, e.g. after losing the device, so their spool is finished
+        if matches!(*session.state.lock().unwrap(), CaptureState::Recording | CaptureState::Paused) {
+            session.stop()?;
+        }
+        Ok(())
+    }
+    
+    // Function to set the selected device
+    pub fn set_selected_device(&self, device_id: Option<String>) {
+        *self.selected_device.lock().unwrap() = device_id;
+    }
+    
+    // Set whether we're using system audio
+    pub fn set_audio_source(&self, is_system: bool) {
+        self.system_audio.store(is_system, Ordering::SeqCst);
+    }
+    
+    // Pick the output system audio is captured from, or the default output if None
+    pub fn set_selected_monitor(&self, monitor_name: Option<String>) {
+        *self.selected_monitor.lock().unwrap() = monitor_name;
+    }
+    
+    pub fn selected_monitor(&self) -> Option<String> {
+        self.selected_monitor.lock().unwrap().clone()
+    }
+    
+    pub fn loopback_rules(&self) -> Vec<DeviceRule> {
+        self.loopback_rules.lock().unwrap().clone()
+    }
+    
+    // Applied to sessions opened from now on
+    pub fn set_loopback_rules(&self, rules: Vec<DeviceRule>) {
+        *self.loopback_rules.lock().unwrap() = rules;
+    }
+    
+    // Where session audio is spooled, and recordings are recovered from
+    pub fn spool_dir(&self) -> &PathBuf {
+        &self.spool_dir
+    }
+    
+    pub fn device_loss(&self) -> DeviceLossPolicy {
+        *self.device_loss.lock().unwrap()
+    }
+    
+    // Applied to sessions opened from now on
+    pub fn set_device_loss(&self, policy: DeviceLossPolicy) {
+        *self.device_loss.lock().unwrap() = policy;
+    }
+    
+    pub fn channel_options(&self) -> ChannelOptions {
+        *self.channel_options.lock().unwrap()
+    }
+    
+    // Applied to sessions opened from now on
+    pub fn set_channel_options(&self, options: ChannelOptions) {
+        *self.channel_options.lock().unwrap() = options;
+    }
+    
+    // Where session audio is saved when recordings are on
+    pub fn recordings_dir(&self) -> &PathBuf {
+        &self.recordings_dir
+    }
+    
+    pub fn recording_options(&self) -> RecordingOptions {
+        self.recording_options.lock().unwrap().clone()
+    }
+    
+    // Applied to sessions opened from now on
+    pub fn set_recording_options(&self, options: RecordingOptions) {
+        *self.recording_options.lock().unwrap() = options;
+    }
+}
+
+// The parts of a session the recording thread works with
+struct CaptureStream {
+    session_id: SessionId,
+    channel_options: ChannelOptions,
+    device_loss: DeviceLossPolicy,
+    tag: Option<SourceTag>,
+    spool_dir: PathBuf,
+    buffer: Arc<CaptureBuffer>,
+    // Cleared while paused; the callback drops samples when it is false
+    capturing: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    overruns: Arc<OverrunCounters>,
+    control: Receiver<Control>,
+    // Handed to stream error callbacks so errors arrive on the control channel
+    errors: Sender<Control>,
+}
+
+impl CaptureStream {
+    // Open `device` with its default config and capture until the session stops.
+    // `device_id` finds the device again if it disconnects. The negotiated format,
+    // or the error, is sent to `ready`.
+    fn run(self, device: cpal::Device, device_id: Option<String>, ready: Sender<Result<CaptureFormat, String>>) {
         let config = match device.default_input_config() {
             Ok(config) => config,
             Err(e) => {
-                eprintln!("Error getting default config: {}", e);
-                is_recording.store(false, Ordering::SeqCst);
+                self.fail(&ready, format!("Error getting default config: {}", e));
                 return;
             }
         };
         
-        // Store the original sample rate
-        *sample_rate.lock().unwrap() = config.sample_rate().0;
+        let format = CaptureFormat {
+            device: device.name().unwrap_or_default(),
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+            sample_format: config.sample_format().to_string(),
+        };
         
-        println!("Original sample rate: {}", config.sample_rate().0);
+        println!("Original sample rate: {}", format.sample_rate);
+        println!("Channels: {}", format.channels);
+        println!("Sample format: {}", format.sample_format);
         
-        // Clone is_recording for the data callback
-        let is_recording_data = is_recording.clone();
-        // Clone is_recording for the error callback
-        let is_recording_error = is_recording.clone();
+        // Lives on this thread for the whole session, so a reconnect carries on where it left off
+        let mut processor = match self.processor(format.sample_rate, format.channels) {
+            Ok(processor) => processor,
+            Err(e) => {
+                self.fail(&ready, e);
+                return;
+            }
+        };
+        let (mut stream, mut queue) = match self.open_stream(&device, &config) {
+            Ok((stream, queue)) => (Some(stream), queue),
+            Err(e) => {
+                self.fail(&ready, e);
+                return;
+            }
+        };
+        
+        let _ = ready.send(Ok(format.clone()));
         
-        let stream = match device.build_input_stream(
-            &config.into(),
-            move |data: &[f32], _| {
-                if is_recording_data.load(Ordering::SeqCst) {
-                    audio_data.lock().unwrap().extend_from_slice(data);
+        // Take in what the callback queues between control messages until the session stops
+        loop {
+            let message = match self.control.recv_timeout(WORKER_INTERVAL) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    drain_queue(&mut queue, &mut processor, format.channels);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => Control::Stop,
+            };
+            // Audio queued before the message belongs to the state before it
+            drain_queue(&mut queue, &mut processor, format.channels);
+            
+            let error = match message {
+                Control::Pause(reply) => {
+                    // Audio the callback queued before it saw the flag still belongs before the gap
+                    self.capturing.store(false, Ordering::SeqCst);
+                    drain_queue(&mut queue, &mut processor, format.channels);
+                    processor.interrupt();
+                    let _ = reply.send(self.captured_len());
+                    continue;
+                }
+                Control::Resume(gap) => {
+                    processor.skip_gap(gap);
+                    self.capturing.store(true, Ordering::SeqCst);
+                    continue;
+                }
+                Control::Stop => break,
+                Control::StreamError(error) => error,
+            };
+            
+            // Only a device that has gone away ends the stream; other errors are passed on
+            let fatal = matches!(error, cpal::StreamError::DeviceNotAvailable);
+            self.report_error(&format.device, &error, fatal);
+            if !fatal {
+                continue;
+            }
+            stream = None;
+            drain_queue(&mut queue, &mut processor, format.channels);
+            
+            let reconnect = self.device_loss == DeviceLossPolicy::Reconnect && device_id.is_some();
+            self.report_loss(&format.device, &error.to_string(), reconnect);
+            if !reconnect {
+                break;
+            }
+            
+            // Wait for the device to come back and reopen it with the same format
+            let reopened = self.wait_for_device(&mut processor, || {
+                let device = device_id.as_deref().and_then(devices::find_input_device)?;
+                self.open_stream(&device, &config)
+                    .map_err(|e| eprintln!("{}", e))
+                    .ok()
+            });
+            match reopened {
+                Some((reopened, reopened_queue)) => {
+                    stream = Some(reopened);
+                    queue = reopened_queue;
+                    println!("Reconnected to {}", format.device);
+                    devices::emit_device_reconnected(self.session_id, &format.device);
                 }
-            },
-            move |err| {
-                eprintln!("Stream error: {}", err);
-                is_recording_error.store(false, Ordering::SeqCst);
-            },
-            None,
-        ) {
-            Ok(stream) => stream,
+                None => break,
+            }
+        }
+        
+        // Close the stream, then take in what it queued before it stopped
+        drop(stream);
+        drain_queue(&mut queue, &mut processor, format.channels);
+        self.running.store(false, Ordering::SeqCst);
+    }
+    
+    // Record from a PulseAudio monitor source until the session stops
+    #[cfg(target_os = "linux")]
+    fn run_monitor(self, monitor: pulse::MonitorSource, ready: Sender<Result<CaptureFormat, String>>) {
+        let mut capture = match pulse::MonitorCapture::open(&monitor) {
+            Ok(capture) => capture,
+            Err(e) => {
+                self.fail(&ready, e);
+                return;
+            }
+        };
+        
+        let format = CaptureFormat {
+            device: monitor.description.clone(),
+            sample_rate: monitor.sample_rate,
+            channels: monitor.channels,
+            sample_format: SampleFormat::F32.to_string(),
+        };
+        println!("Capturing output monitor {} ({}Hz, {} channels)", monitor.name, format.sample_rate, format.channels);
+        
+        let mut processor = match self.processor(format.sample_rate, format.channels) {
+            Ok(processor) => processor,
             Err(e) => {
-                eprintln!("Error building stream: {}", e);
-                is_recording.store(false, Ordering::SeqCst);
+                self.fail(&ready, e);
                 return;
             }
         };
+        let _ = ready.send(Ok(format.clone()));
+        
+        // Reads block for a few milliseconds of audio at a time, so control
+        // messages are picked up between them
+        loop {
+            match self.control.try_recv() {
+                Ok(Control::Pause(reply)) => {
+                    self.capturing.store(false, Ordering::SeqCst);
+                    processor.interrupt();
+                    let _ = reply.send(self.captured_len());
+                }
+                Ok(Control::Resume(gap)) => {
+                    processor.skip_gap(gap);
+                    self.capturing.store(true, Ordering::SeqCst);
+                }
+                Ok(Control::Stop) | Err(mpsc::TryRecvError::Disconnected) => break,
+                Ok(Control::StreamError(_)) | Err(mpsc::TryRecvError::Empty) => {}
+            }
+            
+            let message = match capture.read() {
+                Ok(data) => {
+                    if self.capturing.load(Ordering::SeqCst) {
+                        processor.process(data);
+                    }
+                    continue;
+                }
+                Err(e) => e,
+            };
+            
+            // PulseAudio only fails a read when the stream is gone
+            devices::emit_stream_error(self.session_id, &format.device, StreamErrorKind::Backend, &message, true);
+            let reconnect = self.device_loss == DeviceLossPolicy::Reconnect;
+            self.report_loss(&format.device, &message, reconnect);
+            if !reconnect {
+                break;
+            }
+            
+            // The output must come back with the same format for the recording to carry on
+            let reopened = self.wait_for_device(&mut processor, || {
+                pulse::find_monitor_source(Some(&monitor.name))
+                    .ok()
+                    .filter(|found| found.sample_rate == monitor.sample_rate && found.channels == monitor.channels)
+                    .and_then(|found| pulse::MonitorCapture::open(&found).map_err(|e| eprintln!("{}", e)).ok())
+            });
+            match reopened {
+                Some(reopened) => {
+                    capture = reopened;
+                    println!("Reconnected to {}", format.device);
+                    devices::emit_device_reconnected(self.session_id, &format.device);
+                }
+                None => break,
+            }
+        }
+        self.running.store(false, Ordering::SeqCst);
+    }
+    
+    // Build and start a stream on `device`. Its callback queues audio for the
+    // recording thread; stream errors are sent to it on the control channel.
+    fn open_stream(
+        &self,
+        device: &cpal::Device,
+        config: &cpal::SupportedStreamConfig,
+    ) -> Result<(cpal::Stream, Consumer<f32>), String> {
+        let channels = config.channels().max(1) as usize;
+        let capacity = config.sample_rate().0 as usize * QUEUE_SECS * channels;
+        let (producer, consumer) = RingBuffer::new(capacity);
+        
+        let queue = CallbackQueue {
+            producer,
+            channels,
+            capturing: self.capturing.clone(),
+            overruns: self.overruns.clone(),
+        };
         
-        if let Err(e) = stream.play() {
-            eprintln!("Error playing stream: {}", e);
-            is_recording.store(false, Ordering::SeqCst);
+        let errors = self.errors.clone();
+        let error_callback = move |err: cpal::StreamError| {
+            let _ = errors.send(Control::StreamError(err));
+        };
+        
+        let stream_config: cpal::StreamConfig = config.config();
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(device, &stream_config, queue, error_callback),
+            SampleFormat::F64 => build_stream::<f64>(device, &stream_config, queue, error_callback),
+            SampleFormat::I8 => build_stream::<i8>(device, &stream_config, queue, error_callback),
+            SampleFormat::I16 => build_stream::<i16>(device, &stream_config, queue, error_callback),
+            SampleFormat::I32 => build_stream::<i32>(device, &stream_config, queue, error_callback),
+            SampleFormat::I64 => build_stream::<i64>(device, &stream_config, queue, error_callback),
+            SampleFormat::U8 => build_stream::<u8>(device, &stream_config, queue, error_callback),
+            SampleFormat::U16 => build_stream::<u16>(device, &stream_config, queue, error_callback),
+            SampleFormat::U32 => build_stream::<u32>(device, &stream_config, queue, error_callback),
+            SampleFormat::U64 => build_stream::<u64>(device, &stream_config, queue, error_callback),
+            other => return Err(format!("Unsupported sample format: {}", other)),
+        };
+        
+        let stream = stream.map_err(|e| format!("Error building stream: {}", e))?;
+        stream.play().map_err(|e| format!("Error playing stream: {}", e))?;
+        Ok((stream, consumer))
+    }
+    
+    fn fail(&self, ready: &Sender<Result<CaptureFormat, String>>, message: String) {
+        eprintln!("{}", message);
+        self.running.store(false, Ordering::SeqCst);
+        let _ = ready.send(Err(message));
+    }
+    
+    fn report_error(&self, device: &str, error: &cpal::StreamError, fatal: bool) {
+        eprintln!("Stream error on {}: {}", device, error);
+        let kind = match error {
+            cpal::StreamError::DeviceNotAvailable => StreamErrorKind::DeviceNotAvailable,
+            cpal::StreamError::BackendSpecific { .. } => StreamErrorKind::Backend,
+        };
+        devices::emit_stream_error(self.session_id, device, kind, &error.to_string(), fatal);
+    }
+    
+    // Tell the frontend the device has gone. Unless the session is reconnecting,
+    // the capture stops; the audio recorded so far is kept.
+    fn report_loss(&self, device: &str, message: &str, reconnecting: bool) {
+        eprintln!("Lost {}: {}", device, message);
+        devices::emit_device_lost(self.session_id, device, message, reconnecting);
+    }
+    
+    // Retry `reopen` every second until it succeeds or the session is stopped.
+    // Pausing and resuming still take effect while the device is away.
+    fn wait_for_device<T>(&self, processor: &mut FrameProcessor, mut reopen: impl FnMut() -> Option<T>) -> Option<T> {
+        loop {
+            match self.control.recv_timeout(RECONNECT_INTERVAL) {
+                Ok(Control::Pause(reply)) => {
+                    self.capturing.store(false, Ordering::SeqCst);
+                    processor.interrupt();
+                    let _ = reply.send(self.captured_len());
+                }
+                Ok(Control::Resume(gap)) => {
+                    processor.skip_gap(gap);
+                    self.capturing.store(true, Ordering::SeqCst);
+                }
+                Ok(Control::Stop) | Err(RecvTimeoutError::Disconnected) => return None,
+                // Errors from the stream that has already been closed
+                Ok(Control::StreamError(_)) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(reopened) = reopen() {
+                        return Some(reopened);
+                    }
+                }
+            }
+        }
+    }
+    
+    // Mono samples taken into the session's spool so far
+    fn captured_len(&self) -> usize {
+        self.buffer.audio.lock().unwrap().as_ref().map_or(0, |audio| audio.len())
+    }
+    
+    // Set up the session's spool and the processing for a stream of this format
+    fn processor(&self, sample_rate: u32, channels: u16) -> Result<FrameProcessor, String> {
+        let options = self.channel_options;
+        let kept_channels = options.keep_channels.then_some(channels);
+        let (audio, channel_spool) = spool::create(&self.spool_dir, self.session_id, self.tag, sample_rate, kept_channels)?;
+        *self.buffer.audio.lock().unwrap() = Some(audio);
+        *self.buffer.channels.lock().unwrap() = channel_spool;
+        
+        Ok(FrameProcessor {
+            session_id: self.session_id,
+            mixer: ChannelMixer::new(channels, options.selection),
+            buffer: self.buffer.clone(),
+            vad: VoiceActivityDetector::new(sample_rate),
+            meter: LevelMeter::new(self.session_id, sample_rate, channels, self.overruns.clone()),
+            mono: Vec::new(),
+        })
+    }
+}
+
+// Handles the audio a stream delivers, on the recording thread. Interleaved device frames are reduced to mono
+// for the session buffer and voice activity detection; levels are metered per channel.
+struct FrameProcessor {
+    session_id: SessionId,
+    mixer: ChannelMixer,
+    buffer: Arc<CaptureBuffer>,
+    vad: VoiceActivityDetector,
+    meter: LevelMeter,
+    mono: Vec<f32>,
+}
+
+impl FrameProcessor {
+    fn process(&mut self, data: &[f32]) {
+        self.mono.clear();
+        self.mixer.mix_into(data, &mut self.mono);
+        if let Some(audio) = self.buffer.audio.lock().unwrap().as_mut() {
+            audio.push(&self.mono);
+        }
+        
+        if let Some(channels) = self.buffer.channels.lock().unwrap().as_mut() {
+            channels.push(data);
+        }
+        
+        self.meter.process(data);
+        
+        let events = self.vad.process(&self.mono);
+        if !events.is_empty() {
+            vad::dispatch(self.session_id, events);
+        }
+    }
+    
+    // End speech in progress where the audio breaks off
+    fn interrupt(&mut self) {
+        if let Some(event) = self.vad.interrupt() {
+            vad::dispatch(self.session_id, vec![event]);
+        }
+    }
+    
+    // Move voice activity times past a pause of `seconds`
+    fn skip_gap(&mut self, seconds: f32) {
+        self.vad.skip_gap(seconds);
+    }
+}
+
+// The capture callback's end of the queue to the recording thread. The callback
+// runs on the real-time audio thread, so it never locks or allocates: samples are
+// converted straight into the queue, and frames that don't fit are counted and dropped.
+struct CallbackQueue {
+    producer: Producer<f32>,
+    channels: usize,
+    capturing: Arc<AtomicBool>,
+    overruns: Arc<OverrunCounters>,
+}
+
+impl CallbackQueue {
+    fn push<T>(&mut self, data: &[T])
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        if !self.capturing.load(Ordering::Relaxed) {
             return;
         }
         
-        // Keep the stream alive as long as we're recording
-        while is_recording.load(Ordering::SeqCst) {
-            thread::sleep(std::time::Duration::from_millis(100));
+        // Only whole frames go in, so the recording thread always reads complete frames
+        let frames = data.len() / self.channels;
+        let fit = frames.min(self.producer.slots() / self.channels);
+        if fit < frames {
+            self.overruns.record((frames - fit) as u64);
         }
         
-        // Stream will be dropped when this thread ends
-    });
+        if let Ok(chunk) = self.producer.write_chunk_uninit(fit * self.channels) {
+            chunk.fill_from_iter(data.iter().map(|&sample| sample.to_sample::<f32>()));
+        }
+    }
+}
+
+// Pass everything queued by the callback to `processor`, returning the number of samples
+fn drain_queue(queue: &mut Consumer<f32>, processor: &mut FrameProcessor, channels: u16) -> usize {
+    let channels = channels.max(1) as usize;
+    let available = queue.slots() / channels * channels;
+    let Ok(chunk) = queue.read_chunk(available) else {
+        return 0;
+    };
+    
+    // The queue holds a whole number of frames, so neither half splits one
+    let (first, second) = chunk.as_slices();
+    processor.process(first);
+    if !second.is_empty() {
+        processor.process(second);
+    }
+    chunk.commit_all();
+    available
+}
+
+// Build an input stream for samples of type `T`, queueing them as normalized f32
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut queue: CallbackQueue,
+    error_callback: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[T], _| queue.push(data),
+        error_callback,
+        None,
+    )
+}
+
+// Find the device for a capture source, with its id if it can be told apart from other devices
+fn find_device(source: &CaptureSource) -> Result<(cpal::Device, Option<String>), String> {
+    let host = cpal::default_host();
+    
+    let found = match source {
+        // Get the selected device or default
+        // A device the user picked is never swapped for another one
+        CaptureSource::Device(Some(device_id)) => Some((
+            devices::find_input_device(device_id)
+                .ok_or_else(|| format!("Input device {} is not connected", device_id))?,
+            Some(device_id.clone()),
+        )),
+        // Use default device
+        CaptureSource::Device(None) => default_input_device(&host),
+        CaptureSource::System { rules, .. } => {
+            // Look for virtual audio devices (like BlackHole, VB-Audio, etc.)
+            match find_loopback_device(&host, rules) {
+                Some((device, device_id, matched)) => {
+                    println!("Found virtual audio device: {} ({})", matched.device, matched.reason);
+                    Some((device, Some(device_id)))
+                }
+                None => {
+                    eprintln!("No virtual audio device found, using default input device");
+                    default_input_device(&host)
+                }
+            }
+        }
+    };
     
-    // Store the thread handle
-    *RECORDING_THREAD.lock().unwrap() = Some(handle);
+    let (device, device_id) = found.ok_or_else(|| "No input device available".to_string())?;
+    println!("Using input device: {:?}", device.name().unwrap_or_default());
+    Ok((device, device_id))
+}
+
+fn default_input_device(host: &cpal::Host) -> Option<(cpal::Device, Option<String>)> {
+    let device = host.default_input_device()?;
+    let device_id = devices::id_of(&device);
+    Some((device, device_id))
+}
+
+// The first input device picked by the loopback rules, with its id
+fn find_loopback_device(host: &cpal::Host, rules: &[DeviceRule]) -> Option<(cpal::Device, String, DeviceMatch)> {
+    let (devices, names): (Vec<cpal::Device>, Vec<String>) = host
+        .input_devices()
+        .ok()?
+        .filter_map(|device| {
+            let name = device.name().ok()?;
+            Some((device, name))
+        })
+        .unzip();
     
-    Ok(())
+    let (index, matched) = loopback::find_match(rules, &names)?;
+    let device_id = devices::ids_for(host.id(), &names).swap_remove(index);
+    devices.into_iter().nth(index).map(|device| (device, device_id, matched))
 }
 
-pub fn stop_capture() -> Result<Vec<f32>, String> {
-    // If not recording, return early
-    if !IS_RECORDING.load(Ordering::SeqCst) {
-        return Err("Not recording".to_string());
+// Work out which device system audio would be captured from, and why
+pub fn match_system_device(monitor: Option<&str>, rules: &[DeviceRule]) -> Result<DeviceMatch, String> {
+    #[cfg(target_os = "linux")]
+    if let Ok(monitor) = pulse::find_monitor_source(monitor) {
+        return Ok(DeviceMatch {
+            device: monitor.description,
+            rule_index: None,
+            rule: None,
+            reason: format!("Monitor source {} of the output, captured directly", monitor.name),
+        });
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = monitor;
+    
+    let host = cpal::default_host();
+    if let Some((_, _, matched)) = find_loopback_device(&host, rules) {
+        return Ok(matched);
     }
     
-    // Set recording flag to false
-    IS_RECORDING.store(false, Ordering::SeqCst);
+    let device = host.default_input_device().ok_or_else(|| "No input device available".to_string())?;
+    Ok(DeviceMatch {
+        device: device.name().unwrap_or_default(),
+        rule_index: None,
+        rule: None,
+        reason: "No input device matched a loopback rule, so the default input is used".to_string(),
+    })
+}
+
+// Prepare a recording recovered from the spool for Whisper, in the same pieces as `process_chunks`
+pub fn recovered_chunks(
+    recording: &mut spool::RecoveredRecording,
+    on_chunk: impl FnMut(Vec<f32>, f32, bool) -> Result<(), String>,
+) -> Result<(), String> {
+    let (len, sample_rate) = (recording.len(), recording.sample_rate());
+    prepare_in_chunks(0, len, sample_rate, |from, to| recording.read(from, to), on_chunk)
+}
+
+// Resample samples `from..to` read with `read` to 16kHz a piece at a time, and hand them to
+// `on_chunk` in pieces of up to FINAL_CHUNK_SECS with silence trimmed from each, along with where
+// each starts in seconds and whether it is the last. `on_chunk` is called at least once.
+fn prepare_in_chunks(
+    from: usize,
+    to: usize,
+    sample_rate: u32,
+    mut read: impl FnMut(usize, usize) -> Result<Vec<f32>, String>,
+    mut on_chunk: impl FnMut(Vec<f32>, f32, bool) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut resampler = Resampler::new(sample_rate, 16000, ResampleQuality::Balanced);
+    let read_len = sample_rate as usize * READ_CHUNK_SECS;
+    let chunk_len = 16000 * FINAL_CHUNK_SECS;
+    let start_secs = from as f32 / sample_rate as f32;
+    
+    // Resampled audio not handed on yet, and its position in the resampled stream
+    let mut pending = Vec::new();
+    let mut pending_start = 0;
+    let mut send = |chunk: Vec<f32>, chunk_start: usize, last: bool| {
+        let (chunk, trimmed) = trim_for_whisper(chunk);
+        on_chunk(chunk, start_secs + (chunk_start + trimmed) as f32 / 16000.0, last)
+    };
     
-    // Wait for the recording thread to finish
-    if let Some(handle) = RECORDING_THREAD.lock().unwrap().take() {
-        // Ignore any errors from joining the thread
-        let _ = handle.join();
+    let mut position = from;
+    while position < to {
+        let samples = read(position, (position + read_len).min(to))?;
+        if samples.is_empty() {
+            break;
+        }
+        position += samples.len();
+        pending.extend(resampler.process(&samples));
+        
+        // Something is always held back, so the last piece is never empty
+        while pending.len() > chunk_len {
+            let rest = pending.split_off(chunk_len);
+            send(std::mem::replace(&mut pending, rest), pending_start, false)?;
+            pending_start += chunk_len;
+        }
     }
     
-    // Get a copy of the audio data
-    let audio_data = AUDIO_DATA.lock().unwrap().clone();
+    pending.extend(resampler.flush());
+    send(pending, pending_start, true)
+}
+
+// Resample audio to 16kHz and trim silence for Whisper, also returning how many 16kHz samples
+// were trimmed from the start
+fn prepare_for_whisper_trimmed(audio_data: Vec<f32>, original_sample_rate: u32) -> (Vec<f32>, usize) {
     let audio_data_len = audio_data.len(); // Store the length for later use
     
-    // Get the original sample rate
-    let original_sample_rate = *SAMPLE_RATE.lock().unwrap();
-    
     // If the original sample rate is not 16kHz, resample the audio
     let processed_audio = if original_sample_rate != 16000 {
         println!("Resampling audio from {}Hz to 16000Hz", original_sample_rate);
         
-        // Simple linear resampling
         resample(&audio_data, original_sample_rate, 16000)
     } else {
         // Use the original audio data
         audio_data
     };
     
+    println!("Audio statistics:");
+    println!("  Original length: {} samples", audio_data_len);
+    trim_for_whisper(processed_audio)
+}
+
+// Trim silence from 16kHz audio, also returning how many samples were trimmed from the start
+fn trim_for_whisper(processed_audio: Vec<f32>) -> (Vec<f32>, usize) {
     // Apply voice activity detection to trim silence
-    let vad_audio = trim_silence(&processed_audio, 0.01, 0.5);
+    let (vad_audio, trimmed) = trim_silence(&processed_audio, 0.01, 0.5);
     
     // Print some audio statistics to help with debugging
-    println!("Audio statistics:");
-    println!("  Original length: {} samples", audio_data_len);
     println!("  Processed length: {} samples", processed_audio.len());
     println!("  After VAD: {} samples", vad_audio.len());
     
@@ -330,40 +1265,18 @@ pub fn stop_capture() -> Result<Vec<f32>, String> {
     }
     
     // Return the processed audio data
-    Ok(vad_audio)
+    (vad_audio, trimmed)
 }
 
-// Simple linear resampling function
-fn resample(input: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
-    if from_rate == to_rate {
-        return input.to_vec();
-    }
-    
-    let ratio = to_rate as f64 / from_rate as f64;
-    let output_size = (input.len() as f64 * ratio) as usize;
-    let mut output = Vec::with_capacity(output_size);
-    
-    for i in 0..output_size {
-        let src_idx = i as f64 / ratio;
-        let src_idx_floor = src_idx.floor() as usize;
-        let src_idx_ceil = src_idx.ceil() as usize;
-        
-        if src_idx_ceil >= input.len() {
-            break;
-        }
-        
-        let t = src_idx - src_idx_floor as f64;
-        let sample = input[src_idx_floor] * (1.0 - t as f32) + input[src_idx_ceil] * t as f32;
-        output.push(sample);
-    }
-    
-    output
+// Band-limited resampling of a complete signal
+pub fn resample(input: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    resample::resample(input, from_rate, to_rate, ResampleQuality::Balanced)
 }
 
-// Function to trim silence from the beginning and end of audio
-fn trim_silence(audio: &[f32], threshold: f32, min_duration_sec: f32) -> Vec<f32> {
+// Function to trim silence from the beginning and end of audio. Also returns where the kept audio starts.
+fn trim_silence(audio: &[f32], threshold: f32, min_duration_sec: f32) -> (Vec<f32>, usize) {
     if audio.is_empty() {
-        return Vec::new();
+        return (Vec::new(), 0);
     }
     
     let sample_rate = 16000; // We're working with 16kHz audio at this point
@@ -384,7 +1297,7 @@ fn trim_silence(audio: &[f32], threshold: f32, min_duration_sec: f32) -> Vec<f32
     // If we didn't find any non-silent samples, return the original audio
     if start_idx >= end_idx {
         println!("No non-silent audio detected, using original audio");
-        return audio.to_vec();
+        return (audio.to_vec(), 0);
     }
     
     // Add some padding around the speech (100ms before and after)
@@ -404,60 +1317,58 @@ fn trim_silence(audio: &[f32], threshold: f32, min_duration_sec: f32) -> Vec<f32
     }
     
     // Return the trimmed audio
-    audio[start_idx..=end_idx].to_vec()
+    (audio[start_idx..=end_idx].to_vec(), start_idx)
 }
 
-// Add this new function to get available input devices
-pub fn get_input_devices() -> Result<Vec<(String, String)>, String> {
-    let host = cpal::default_host();
-    
-    // Get available input devices
-    let devices = match host.input_devices() {
-        Ok(devices) => devices,
-        Err(e) => return Err(format!("Error getting input devices: {}", e)),
-    };
-    
-    // Collect device names and IDs
-    let mut device_list = Vec::new();
-    for device in devices {
-        let name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
-        // Use the name as the ID for now
-        device_list.push((name.clone(), name));
-    }
-    
-    // If no devices were found, return an error
-    if device_list.is_empty() {
-        return Err("No input devices found".to_string());
+// Function to check if system audio is available
+pub fn is_system_audio_available(rules: &[DeviceRule]) -> bool {
+    // Any output's monitor will do on Linux
+    #[cfg(target_os = "linux")]
+    if pulse::list_monitor_sources().is_ok_and(|monitors| !monitors.is_empty()) {
+        return true;
     }
     
-    Ok(device_list)
+    find_loopback_device(&cpal::default_host(), rules).is_some()
 }
 
-// Function to set the selected device
-pub fn set_selected_device(device_name: Option<String>) {
-    let mut selected_device = SELECTED_DEVICE.lock().unwrap();
-    *selected_device = device_name;
-}
-
-// Function to check if system audio is available
-pub fn is_system_audio_available() -> bool {
-    let host = cpal::default_host();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::Write;
     
-    // Look for virtual audio devices (like BlackHole, VB-Audio, etc.)
-    let virtual_device_names = [
-        "BlackHole", "VB-Audio", "CABLE Output", "Soundflower", "Virtual Audio", "Loopback"
-    ];
-    
-    if let Ok(devices) = host.input_devices() {
-        for device in devices {
-            if let Ok(name) = device.name() {
-                // Check if the device name contains any of our virtual device keywords
-                if virtual_device_names.iter().any(|&vname| name.contains(vname)) {
-                    return true;
-                }
-            }
-        }
+    #[test]
+    fn recovers_spool_longer_than_a_piece() {
+        let dir = std::env::temp_dir().join(format!("disrust-recovery-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        
+        // A 16kHz session that crashed a little after its first piece, before the header was updated
+        let samples: Vec<f32> = (0..16000 * (FINAL_CHUNK_SECS + 30))
+            .map(|i| if i % 32 < 16 { 0.5 } else { -0.5 })
+            .collect();
+        let id = "1700000000000-1-me";
+        let mut file = File::create(dir.join(format!("{}.partial.wav", id))).unwrap();
+        spool::write_header(&mut file, 16000, 1, 0).unwrap();
+        let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        file.write_all(&bytes).unwrap();
+        drop(file);
+        
+        let mut recording = spool::open_recoverable(&dir, id).unwrap();
+        assert_eq!(recording.source(), Some(SourceTag::Me));
+        
+        let mut pieces = Vec::new();
+        recovered_chunks(&mut recording, |chunk, at, last| {
+            pieces.push((chunk, at, last));
+            Ok(())
+        })
+        .unwrap();
+        drop(recording);
+        fs::remove_dir_all(&dir).unwrap();
+        
+        let positions: Vec<(f32, bool)> = pieces.iter().map(|(_, at, last)| (*at, *last)).collect();
+        assert_eq!(positions, [(0.0, false), (FINAL_CHUNK_SECS as f32, true)]);
+        
+        let recovered: Vec<f32> = pieces.into_iter().flat_map(|(chunk, _, _)| chunk).collect();
+        assert!(recovered == samples, "The recovered audio differs from what was spooled");
     }
-    
-    false
 }
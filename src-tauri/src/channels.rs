@@ -0,0 +1,73 @@
+// Turning interleaved multi-channel capture data into the mono signal we transcribe
+
+use serde::{Deserialize, Serialize};
+
+// Which part of a multi-channel input is transcribed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum ChannelSelection {
+    // Average all channels
+    #[default]
+    Downmix,
+    // A single channel, numbered from 0
+    Channel { index: usize },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ChannelOptions {
+    pub selection: ChannelSelection,
+    // Also keep a separate buffer for every channel of the capture
+    pub keep_channels: bool,
+}
+
+// Converts interleaved frames to mono according to a ChannelSelection
+pub struct ChannelMixer {
+    channels: usize,
+    selection: ChannelSelection,
+}
+
+impl ChannelMixer {
+    pub fn new(channels: u16, selection: ChannelSelection) -> Self {
+        let channels = channels.max(1) as usize;
+
+        // A channel the device doesn't have falls back to the mix rather than silence
+        let selection = match selection {
+            ChannelSelection::Channel { index } if index >= channels => {
+                eprintln!("Input has no channel {} ({} channels), downmixing instead", index + 1, channels);
+                ChannelSelection::Downmix
+            }
+            selection => selection,
+        };
+
+        ChannelMixer { channels, selection }
+    }
+
+    // Append the mono version of `data` to `output`
+    pub fn mix_into(&self, data: &[f32], output: &mut Vec<f32>) {
+        if self.channels == 1 {
+            output.extend_from_slice(data);
+            return;
+        }
+
+        let frames = data.chunks_exact(self.channels);
+        match self.selection {
+            ChannelSelection::Downmix => {
+                let scale = 1.0 / self.channels as f32;
+                output.extend(frames.map(|frame| frame.iter().sum::<f32>() * scale));
+            }
+            ChannelSelection::Channel { index } => {
+                output.extend(frames.map(|frame| frame[index]));
+            }
+        }
+    }
+}
+
+// Append each channel of interleaved `data` to its own buffer
+pub fn split_into(data: &[f32], buffers: &mut [Vec<f32>]) {
+    let channels = buffers.len().max(1);
+    for frame in data.chunks_exact(channels) {
+        for (buffer, &sample) in buffers.iter_mut().zip(frame) {
+            buffer.push(sample);
+        }
+    }
+}
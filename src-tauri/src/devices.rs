@@ -0,0 +1,245 @@
+// Input device enumeration with stable ids, and a watcher that reports inputs
+// being plugged in or removed.
+//
+// cpal has no persistent device identifier, so ids are built from the audio host
+// and the device name, numbered when several devices share a name. They stay the
+// same across restarts and reconnects as long as the system keeps the name.
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+use cpal::traits::{DeviceTrait, HostTrait};
+use tauri::{AppHandle, Emitter};
+use crate::audio::SessionId;
+
+// How often the watcher looks for added or removed inputs
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+static APP: OnceCell<AppHandle> = OnceCell::new();
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDevice {
+    pub id: String,
+    pub name: String,
+    pub channels: u16,
+}
+
+// What a capture does when its device disappears mid-session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceLossPolicy {
+    // Stop the capture and report the error
+    #[default]
+    Fail,
+    // Keep the session open and resume when the device comes back
+    Reconnect,
+}
+
+// Payload for the `input-devices-changed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct DevicesChangedEvent {
+    pub added: Vec<InputDevice>,
+    // Ids of the inputs that went away
+    pub removed: Vec<String>,
+    pub devices: Vec<InputDevice>,
+}
+
+// What went wrong with a capture stream
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamErrorKind {
+    // The device was unplugged or otherwise went away
+    DeviceNotAvailable,
+    // Any other error reported by the audio backend
+    Backend,
+}
+
+// Payload for the `capture-stream-error` event
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamErrorEvent {
+    pub session_id: SessionId,
+    pub device: String,
+    pub kind: StreamErrorKind,
+    pub message: String,
+    // Whether the stream was closed; it is followed by `capture-device-lost` if so
+    pub fatal: bool,
+}
+
+// Payload for the `capture-device-lost` event
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceLostEvent {
+    pub session_id: SessionId,
+    pub device: String,
+    pub message: String,
+    // Whether the session is waiting for the device to come back
+    pub reconnecting: bool,
+}
+
+// Payload for the `capture-device-reconnected` event
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceReconnectedEvent {
+    pub session_id: SessionId,
+    pub device: String,
+}
+
+// Start the watcher thread; called once from the app setup
+pub fn init(app: AppHandle) {
+    let _ = APP.set(app.clone());
+    thread::spawn(move || watch(app));
+}
+
+// Every input device with its id, without opening any of them
+fn enumerate() -> Result<Vec<(String, cpal::Device)>, String> {
+    let host = cpal::default_host();
+    let devices = host
+        .input_devices()
+        .map_err(|e| format!("Error getting input devices: {}", e))?;
+
+    let (devices, names): (Vec<cpal::Device>, Vec<String>) = devices
+        .map(|device| {
+            let name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
+            (device, name)
+        })
+        .unzip();
+    Ok(ids_for(host.id(), &names).into_iter().zip(devices).collect())
+}
+
+// Ids of devices named `names`, in the host's order, numbering repeated names
+pub fn ids_for(host: cpal::HostId, names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let occurrence = names[..index].iter().filter(|seen| *seen == name).count();
+            device_id(host, name, occurrence)
+        })
+        .collect()
+}
+
+fn device_id(host: cpal::HostId, name: &str, occurrence: usize) -> String {
+    let host = host.name().to_lowercase();
+    if occurrence == 0 {
+        format!("{}:{}", host, name)
+    } else {
+        format!("{}:{}#{}", host, name, occurrence + 1)
+    }
+}
+
+fn describe(id: String, device: &cpal::Device) -> InputDevice {
+    InputDevice {
+        id,
+        name: device.name().unwrap_or_else(|_| "Unknown Device".to_string()),
+        channels: device.default_input_config().map(|config| config.channels()).unwrap_or(1),
+    }
+}
+
+pub fn list_input_devices() -> Result<Vec<InputDevice>, String> {
+    let devices: Vec<InputDevice> = enumerate()?
+        .into_iter()
+        .map(|(id, device)| describe(id, &device))
+        .collect();
+
+    if devices.is_empty() {
+        return Err("No input devices found".to_string());
+    }
+    Ok(devices)
+}
+
+// The connected device with the given id
+pub fn find_input_device(id: &str) -> Option<cpal::Device> {
+    enumerate()
+        .ok()?
+        .into_iter()
+        .find(|(device_id, _)| device_id == id)
+        .map(|(_, device)| device)
+}
+
+// The id of a device found by other means than its id, e.g. the default input, so it can
+// be found again after it disconnects. None if several devices share its name, since
+// there is then no telling which of them it is.
+pub fn id_of(device: &cpal::Device) -> Option<String> {
+    let name = device.name().ok()?;
+    let mut matches = enumerate()
+        .ok()?
+        .into_iter()
+        .filter(|(_, other)| other.name().is_ok_and(|other| other == name));
+
+    let (id, _) = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(id)
+}
+
+fn watch(app: AppHandle) {
+    let mut known = enumerate().unwrap_or_default();
+
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+
+        let current = match enumerate() {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+
+        let added: Vec<InputDevice> = current
+            .iter()
+            .filter(|(id, _)| !known.iter().any(|(known_id, _)| known_id == id))
+            .map(|(id, device)| describe(id.clone(), device))
+            .collect();
+        let removed: Vec<String> = known
+            .iter()
+            .filter(|(id, _)| !current.iter().any(|(current_id, _)| current_id == id))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        if !added.is_empty() || !removed.is_empty() {
+            let devices = current.iter().map(|(id, device)| describe(id.clone(), device)).collect();
+            emit(&app, "input-devices-changed", DevicesChangedEvent { added, removed, devices });
+        }
+
+        known = current;
+    }
+}
+
+pub fn emit_stream_error(session_id: SessionId, device: &str, kind: StreamErrorKind, message: &str, fatal: bool) {
+    if let Some(app) = APP.get() {
+        emit(app, "capture-stream-error", StreamErrorEvent {
+            session_id,
+            device: device.to_string(),
+            kind,
+            message: message.to_string(),
+            fatal,
+        });
+    }
+}
+
+pub fn emit_device_lost(session_id: SessionId, device: &str, message: &str, reconnecting: bool) {
+    if let Some(app) = APP.get() {
+        emit(app, "capture-device-lost", DeviceLostEvent {
+            session_id,
+            device: device.to_string(),
+            message: message.to_string(),
+            reconnecting,
+        });
+    }
+}
+
+pub fn emit_device_reconnected(session_id: SessionId, device: &str) {
+    if let Some(app) = APP.get() {
+        emit(app, "capture-device-reconnected", DeviceReconnectedEvent {
+            session_id,
+            device: device.to_string(),
+        });
+    }
+}
+
+fn emit<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    if let Err(e) = app.emit(event, payload) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
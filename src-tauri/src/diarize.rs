@@ -1,6 +1,13 @@
 // Implementation for speaker diarization
 
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use crate::embedding::{SpeakerEmbedder, SpeakerEmbeddings, EMBEDDING_MODEL_FILE};
+use crate::features::FeatureExtractor;
+use crate::speakers::{SpeakerProfile, SpeakerStore};
+use crate::transcript::{Segment, Speaker, Word};
 
 // Structure to hold speaker segments
 #[derive(Debug, Clone)]
@@ -14,99 +21,139 @@ pub struct SpeakerSegment {
 #[derive(Debug, Clone)]
 pub struct DiarizationResult {
     pub segments: Vec<SpeakerSegment>,
+    // Confidence of each speaker's assignments, from 0 to 1
+    pub confidence: HashMap<String, f32>,
+    // Display names of speakers matched to enrolled profiles
+    pub names: HashMap<String, String>,
 }
 
-// Improved feature extraction for speaker identification
-fn extract_features(samples: &[f32], sample_rate: u32) -> Vec<Vec<f32>> {
-    // Use a more sophisticated feature extraction approach
-    // This implementation uses multiple features per window:
-    // 1. Energy (volume)
-    // 2. Zero-crossing rate (frequency characteristic)
-    // 3. Spectral centroid (brightness of sound)
+// How speaker vectors are grouped into speakers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusteringMode {
+    // Greedy assignment in recording order
+    Online,
+    // Average-linkage agglomerative clustering with speaker-count estimation
+    Agglomerative,
+}
+
+// User-adjustable clustering settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusteringOptions {
+    pub mode: ClusteringMode,
+    // Bounds on the number of speakers; estimated from the audio when unset
+    pub min_speakers: Option<usize>,
+    pub max_speakers: Option<usize>,
+}
+
+impl Default for ClusteringOptions {
+    fn default() -> Self {
+        ClusteringOptions {
+            mode: ClusteringMode::Agglomerative,
+            min_speakers: None,
+            max_speakers: None,
+        }
+    }
+}
+
+// Kind of speaker vector, which decides how vectors are compared
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorKind {
+    Mfcc,
+    Embedding,
+}
+
+impl VectorKind {
+    fn threshold(self) -> f32 {
+        match self {
+            VectorKind::Mfcc => MFCC_SPEAKER_THRESHOLD,
+            VectorKind::Embedding => EMBEDDING_SPEAKER_THRESHOLD,
+        }
+    }
+}
+
+// One speaker vector per speech segment
+#[derive(Debug, Clone)]
+pub struct SpeakerVectors {
+    pub kind: VectorKind,
+    pub vectors: Vec<Vec<f32>>,
+}
+
+// Number of mel bands and cepstral coefficients for the MFCC features
+const N_MELS: usize = 40;
+const N_MFCC: usize = 20;
+
+// Cosine distance below which a segment is assigned to an existing speaker.
+// Standardized MFCC statistics are noisier than neural embeddings, so they get more slack.
+const MFCC_SPEAKER_THRESHOLD: f32 = 0.7;
+const EMBEDDING_SPEAKER_THRESHOLD: f32 = 0.5;
+
+// Speaker limit for online clustering when no maximum is set
+const MAX_ONLINE_SPEAKERS: usize = 8;
+
+// Calculate the cosine distance between two feature vectors
+fn feature_distance(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
     
-    let window_size = (sample_rate as f32 * 0.025) as usize; // 25ms window
-    let hop_size = (sample_rate as f32 * 0.010) as usize; // 10ms hop
+    if norm_a < 1e-9 || norm_b < 1e-9 {
+        return 1.0;
+    }
     
-    let mut feature_vectors = Vec::new();
+    1.0 - dot / (norm_a * norm_b)
+}
+
+// Standardize each dimension across all segments so that no single MFCC statistic
+// dominates the distance, and the session's average voice sits at the origin.
+// Returns the mean and standard deviation of each dimension, or nothing if there
+// are too few segments to standardize.
+fn standardize(features: &mut [Vec<f32>]) -> Vec<(f32, f32)> {
+    if features.len() < 2 {
+        return Vec::new();
+    }
     
-    for i in (0..samples.len()).step_by(hop_size) {
-        if i + window_size > samples.len() {
-            break;
-        }
-        
-        let window = &samples[i..i+window_size];
-        let mut features = Vec::new();
-        
-        // Feature 1: Energy (volume)
-        let energy: f32 = window.iter().map(|&s| s * s).sum();
-        features.push(energy.sqrt());
-        
-        // Feature 2: Zero-crossing rate
-        let mut zcr = 0.0;
-        for j in 1..window.len() {
-            if (window[j] >= 0.0 && window[j-1] < 0.0) || 
-               (window[j] < 0.0 && window[j-1] >= 0.0) {
-                zcr += 1.0;
-            }
-        }
-        zcr /= window.len() as f32;
-        features.push(zcr);
+    let dims = features[0].len();
+    let count = features.len() as f32;
+    let mut stats = Vec::with_capacity(dims);
+    
+    for d in 0..dims {
+        let mean = features.iter().map(|f| f[d]).sum::<f32>() / count;
+        let variance = features.iter().map(|f| (f[d] - mean).powi(2)).sum::<f32>() / count;
+        let std_dev = variance.sqrt().max(1e-6);
         
-        // Feature 3: Spectral centroid approximation
-        // This is a simplified version - a real implementation would use FFT
-        let mut weighted_sum = 0.0;
-        let mut sum = 0.0;
-        for j in 0..window.len() {
-            let weight = j as f32 / window.len() as f32; // Frequency proxy
-            weighted_sum += window[j].abs() * weight;
-            sum += window[j].abs();
+        for feature in features.iter_mut() {
+            feature[d] = (feature[d] - mean) / std_dev;
         }
-        let spectral_centroid = if sum > 0.0 { weighted_sum / sum } else { 0.0 };
-        features.push(spectral_centroid);
-        
-        feature_vectors.push(features);
+        stats.push((mean, std_dev));
     }
     
-    feature_vectors
+    stats
 }
 
-// Calculate distance between two feature vectors
-fn feature_distance(a: &[f32], b: &[f32]) -> f32 {
-    // Euclidean distance with feature weighting
-    let weights = [0.6, 0.3, 0.1]; // Weights for energy, ZCR, spectral centroid
-    
-    let mut sum_sq = 0.0;
-    for i in 0..a.len().min(b.len()).min(weights.len()) {
-        let diff = a[i] - b[i];
-        sum_sq += weights[i] * diff * diff;
+// Apply the standardization of a recording to another vector, e.g. an enrolled profile
+fn apply_standardization(vector: &mut [f32], stats: &[(f32, f32)]) {
+    for (value, (mean, std_dev)) in vector.iter_mut().zip(stats) {
+        *value = (*value - mean) / std_dev;
     }
-    
-    sum_sq.sqrt()
 }
 
-// Improved clustering algorithm for speaker identification
-fn cluster_features(features: &[Vec<f32>], segment_times: &[(f32, f32)]) -> Vec<SpeakerSegment> {
-    // Use a more robust clustering approach
+// Greedy online clustering: each segment joins the closest speaker seen so far
+fn cluster_online(features: &[Vec<f32>], new_speaker_threshold: f32, max_speakers: usize) -> Vec<usize> {
     // This implementation uses a simple but effective method:
     // 1. Start with first segment as first speaker
     // 2. For each subsequent segment, compare to all existing speaker profiles
     // 3. Assign to closest speaker if distance is below threshold, otherwise create new speaker
     
-    if features.is_empty() || segment_times.is_empty() {
-        return Vec::new();
-    }
-    
-    let mut segments = Vec::new();
+    let mut labels = Vec::with_capacity(features.len());
     let mut speaker_profiles: HashMap<usize, Vec<Vec<f32>>> = HashMap::new();
     
-    // Threshold for considering a new speaker
-    let new_speaker_threshold = 0.5;
-    
     // Minimum segments needed to consider a speaker stable
     let min_segments_for_stability = 3;
     
     // Process each segment
-    for (i, feature) in features.iter().enumerate() {
+    for feature in features {
         // Find the closest speaker profile
         let mut closest_speaker = 0;
         let mut min_distance = f32::MAX;
@@ -138,31 +185,306 @@ fn cluster_features(features: &[Vec<f32>], segment_times: &[(f32, f32)]) -> Vec<
         }
         
         // Determine speaker ID
-        let speaker_id = if is_new_speaker && speaker_profiles.len() < 8 {
-            // Create a new speaker (limit to 8 speakers for UI colors)
+        let speaker_id = if is_new_speaker && speaker_profiles.len() < max_speakers {
             let new_id = speaker_profiles.len();
             speaker_profiles.insert(new_id, vec![feature.clone()]);
             new_id
         } else {
             // Add this segment to the closest speaker's profile
             speaker_profiles.entry(closest_speaker)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(feature.clone());
             closest_speaker
         };
         
-        // Create segment with consistent speaker ID
-        segments.push(SpeakerSegment {
-            start_time: segment_times[i].0,
-            end_time: segment_times[i].1,
-            speaker_id: format!("Speaker{}", speaker_id + 1),
-        });
+        labels.push(speaker_id);
+    }
+    
+    labels
+}
+
+// Offline average-linkage agglomerative clustering over the whole recording.
+// The speaker count is estimated by stopping once the closest clusters are further
+// apart than the threshold, within the optional min/max speaker bounds.
+fn cluster_agglomerative(
+    features: &[Vec<f32>],
+    threshold: f32,
+    min_speakers: Option<usize>,
+    max_speakers: Option<usize>,
+) -> Vec<usize> {
+    let n = features.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    
+    let min_speakers = min_speakers.unwrap_or(1).clamp(1, n);
+    let max_speakers = max_speakers.unwrap_or(n).clamp(min_speakers, n);
+    
+    // Build the full dendrogram, then replay its merges in order of distance
+    let mut merges = linkage_merges(features);
+    merges.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+    
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mut clusters = n;
+    
+    for (a, b, distance) in merges {
+        if clusters <= min_speakers || (clusters <= max_speakers && distance > threshold) {
+            break;
+        }
+        
+        let root_a = find_root(&mut parent, a);
+        let root_b = find_root(&mut parent, b);
+        parent[root_b] = root_a;
+        clusters -= 1;
     }
     
-    // Post-processing: smooth out speaker assignments
-    smooth_speaker_assignments(&mut segments);
+    (0..n).map(|i| find_root(&mut parent, i)).collect()
+}
+
+// Average-linkage merges found with the nearest-neighbour chain algorithm, O(n²).
+// Each merge is (cluster, cluster, distance), where a cluster is named by one of its members.
+fn linkage_merges(features: &[Vec<f32>]) -> Vec<(usize, usize, f32)> {
+    let n = features.len();
+    let mut distances = pairwise_distances(features);
+    let mut sizes = vec![1usize; n];
+    let mut active = vec![true; n];
+    let mut chain: Vec<usize> = Vec::new();
+    let mut merges = Vec::with_capacity(n.saturating_sub(1));
+    
+    while merges.len() + 1 < n {
+        if chain.is_empty() {
+            chain.push(active.iter().position(|&a| a).unwrap_or(0));
+        }
+        
+        let current = chain[chain.len() - 1];
+        let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+        
+        // Nearest active neighbour, preferring the previous chain element on ties
+        let mut nearest = previous.unwrap_or(current);
+        let mut nearest_distance = previous.map(|p| distances[current * n + p]).unwrap_or(f32::MAX);
+        for other in (0..n).filter(|&k| active[k] && k != current) {
+            if distances[current * n + other] < nearest_distance {
+                nearest = other;
+                nearest_distance = distances[current * n + other];
+            }
+        }
+        
+        if Some(nearest) != previous {
+            chain.push(nearest);
+            continue;
+        }
+        
+        // Reciprocal nearest neighbours: merge `current` into `nearest`
+        chain.truncate(chain.len() - 2);
+        let (size_a, size_b) = (sizes[current] as f32, sizes[nearest] as f32);
+        for other in (0..n).filter(|&k| active[k] && k != current && k != nearest) {
+            let merged = (size_a * distances[current * n + other] + size_b * distances[nearest * n + other]) / (size_a + size_b);
+            distances[nearest * n + other] = merged;
+            distances[other * n + nearest] = merged;
+        }
+        sizes[nearest] += sizes[current];
+        active[current] = false;
+        merges.push((nearest, current, nearest_distance));
+    }
     
-    segments
+    merges
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// Row-major matrix of distances between every pair of feature vectors
+fn pairwise_distances(features: &[Vec<f32>]) -> Vec<f32> {
+    let n = features.len();
+    let mut distances = vec![0.0; n * n];
+    
+    for i in 0..n {
+        for j in i + 1..n {
+            let distance = feature_distance(&features[i], &features[j]);
+            distances[i * n + j] = distance;
+            distances[j * n + i] = distance;
+        }
+    }
+    
+    distances
+}
+
+// Renumber cluster labels 0, 1, 2... in order of first appearance
+fn renumber(labels: &[usize]) -> Vec<usize> {
+    let mut order: HashMap<usize, usize> = HashMap::new();
+    labels.iter()
+        .map(|label| {
+            let next = order.len();
+            *order.entry(*label).or_insert(next)
+        })
+        .collect()
+}
+
+// How well each speaker's segments fit their cluster, from 0 to 1.
+// Uses the mean silhouette of the speaker's segments; with a single speaker,
+// the mean similarity to the other segments.
+fn speaker_confidence(features: &[Vec<f32>], labels: &[usize]) -> Vec<f32> {
+    let n_speakers = labels.iter().max().map(|max| max + 1).unwrap_or(0);
+    let mut sizes = vec![0usize; n_speakers];
+    for &label in labels {
+        sizes[label] += 1;
+    }
+    
+    let mut totals = vec![0.0f32; n_speakers];
+    
+    for (i, feature) in features.iter().enumerate() {
+        // Mean distance from this segment to every speaker
+        let mut sums = vec![0.0f32; n_speakers];
+        for (j, other) in features.iter().enumerate() {
+            if i != j {
+                sums[labels[j]] += feature_distance(feature, other);
+            }
+        }
+        
+        let own = labels[i];
+        let own_count = sizes[own] - 1;
+        let a = if own_count > 0 { sums[own] / own_count as f32 } else { 0.0 };
+        
+        let b = (0..n_speakers)
+            .filter(|&s| s != own && sizes[s] > 0)
+            .map(|s| sums[s] / sizes[s] as f32)
+            .fold(f32::MAX, f32::min);
+        
+        let score = if n_speakers == 1 {
+            1.0 - a
+        } else if own_count == 0 {
+            // Silhouette is undefined for singletons
+            0.5
+        } else {
+            ((b - a) / a.max(b).max(1e-9) + 1.0) / 2.0
+        };
+        
+        totals[own] += score.clamp(0.0, 1.0);
+    }
+    
+    totals.iter()
+        .zip(&sizes)
+        .map(|(total, &size)| if size > 0 { total / size as f32 } else { 0.0 })
+        .collect()
+}
+
+// Match speakers to enrolled profiles by the distance between each speaker's mean
+// vector and the profile. Each profile labels at most one speaker.
+fn match_profiles(features: &[Vec<f32>], labels: &[usize], profiles: &[Vec<f32>], threshold: f32) -> HashMap<usize, usize> {
+    let n_speakers = labels.iter().max().map(|max| max + 1).unwrap_or(0);
+    let dims = features.first().map(|f| f.len()).unwrap_or(0);
+    
+    let mut centroids = vec![vec![0.0f32; dims]; n_speakers];
+    for (feature, &label) in features.iter().zip(labels) {
+        for (total, value) in centroids[label].iter_mut().zip(feature) {
+            *total += value;
+        }
+    }
+    
+    let mut candidates = Vec::new();
+    for (speaker, centroid) in centroids.iter().enumerate() {
+        for (profile, embedding) in profiles.iter().enumerate() {
+            // Cosine distance ignores scale, so the centroid needn't be divided by its count
+            let distance = feature_distance(centroid, embedding);
+            if distance < threshold {
+                candidates.push((speaker, profile, distance));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+    
+    let mut matches = HashMap::new();
+    for (speaker, profile, _) in candidates {
+        if !matches.contains_key(&speaker) && !matches.values().any(|&p| p == profile) {
+            matches.insert(speaker, profile);
+        }
+    }
+    
+    matches
+}
+
+// Cluster one speaker vector per segment into speakers, labelling voices
+// that match an enrolled profile with the profile's id and name. The segments
+// are left unsmoothed, since only the caller knows which of them are contiguous.
+fn cluster_vectors(
+    vectors: &SpeakerVectors,
+    segment_times: &[(f32, f32)],
+    options: &ClusteringOptions,
+    profiles: &[SpeakerProfile],
+) -> DiarizationResult {
+    if vectors.vectors.is_empty() || segment_times.is_empty() {
+        return DiarizationResult {
+            segments: Vec::new(),
+            confidence: HashMap::new(),
+            names: HashMap::new(),
+        };
+    }
+    
+    let dims = vectors.vectors[0].len();
+    let profiles: Vec<&SpeakerProfile> = profiles.iter()
+        .filter(|profile| profile.kind == vectors.kind && profile.embedding.len() == dims)
+        .collect();
+    let mut profile_vectors: Vec<Vec<f32>> = profiles.iter().map(|profile| profile.embedding.clone()).collect();
+    
+    let mut features = vectors.vectors.clone();
+    if vectors.kind == VectorKind::Mfcc {
+        let stats = standardize(&mut features);
+        for vector in profile_vectors.iter_mut() {
+            apply_standardization(vector, &stats);
+        }
+    }
+    let threshold = vectors.kind.threshold();
+    
+    let labels = match options.mode {
+        ClusteringMode::Online => {
+            cluster_online(&features, threshold, options.max_speakers.unwrap_or(MAX_ONLINE_SPEAKERS).max(1))
+        }
+        ClusteringMode::Agglomerative => {
+            cluster_agglomerative(&features, threshold, options.min_speakers, options.max_speakers)
+        }
+    };
+    let labels = renumber(&labels);
+    
+    // Enrolled voices keep their profile id; the rest are numbered in order of appearance
+    let matches = match_profiles(&features, &labels, &profile_vectors, threshold);
+    let n_speakers = labels.iter().max().map(|max| max + 1).unwrap_or(0);
+    let mut names = HashMap::new();
+    let mut next_number = 1;
+    let speaker_ids: Vec<String> = (0..n_speakers)
+        .map(|label| match matches.get(&label) {
+            Some(&profile) => {
+                names.insert(profiles[profile].id.clone(), profiles[profile].name.clone());
+                profiles[profile].id.clone()
+            }
+            None => {
+                next_number += 1;
+                format!("Speaker{}", next_number - 1)
+            }
+        })
+        .collect();
+    
+    let confidence = speaker_confidence(&features, &labels)
+        .into_iter()
+        .enumerate()
+        .map(|(label, confidence)| (speaker_ids[label].clone(), confidence))
+        .collect();
+    
+    // Create segments with consistent speaker IDs
+    let segments: Vec<SpeakerSegment> = labels.iter()
+        .zip(segment_times)
+        .map(|(&label, &(start_time, end_time))| SpeakerSegment {
+            start_time,
+            end_time,
+            speaker_id: speaker_ids[label].clone(),
+        })
+        .collect();
+    
+    DiarizationResult { segments, confidence, names }
 }
 
 // Smooth out speaker assignments to avoid rapid switching
@@ -185,27 +507,441 @@ fn smooth_speaker_assignments(segments: &mut [SpeakerSegment]) {
     }
 }
 
-// Main diarization function
-pub fn diarize(samples: &[f32], sample_rate: u32) -> DiarizationResult {
-    // Step 1: Segment the audio based on silence
+// A speaker diarization backend
+pub trait Diarizer: Send + Sync {
+    // Short name shown in logs
+    fn name(&self) -> &'static str;
+    
+    // One speaker vector for each range of samples
+    fn speaker_vectors(&self, samples: &[f32], sample_rate: u32, ranges: &[Range<usize>]) -> Result<SpeakerVectors, String>;
+    
+    // Work out who is speaking when in mono audio
+    fn diarize(&self, samples: &[f32], sample_rate: u32, options: &ClusteringOptions) -> Result<DiarizationResult, String> {
+        let ranges = speech_ranges(samples, sample_rate);
+        let vectors = self.speaker_vectors(samples, sample_rate, &ranges)?;
+        
+        let mut result = cluster_vectors(&vectors, &range_times(&ranges, sample_rate), options, &[]);
+        
+        // Post-processing: smooth out speaker assignments
+        smooth_speaker_assignments(&mut result.segments);
+        Ok(result)
+    }
+}
+
+// Fallback backend: clusters MFCC statistics, needs no model
+pub struct HeuristicDiarizer;
+
+impl Diarizer for HeuristicDiarizer {
+    fn name(&self) -> &'static str {
+        "heuristic"
+    }
+    
+    fn speaker_vectors(&self, samples: &[f32], sample_rate: u32, ranges: &[Range<usize>]) -> Result<SpeakerVectors, String> {
+        let extractor = FeatureExtractor::new(sample_rate, N_MELS, N_MFCC);
+        
+        // Segments are long enough that every one yields statistics
+        let vectors = ranges.iter()
+            .map(|range| extractor.mfcc_statistics(&samples[range.clone()]).unwrap_or_default())
+            .collect();
+        
+        Ok(SpeakerVectors { kind: VectorKind::Mfcc, vectors })
+    }
+}
+
+// Neural backend: clusters embeddings from the ONNX speaker embedding model
+pub struct NeuralDiarizer {
+    embedder: Arc<SpeakerEmbedder>,
+}
+
+impl NeuralDiarizer {
+    pub fn new(embedder: Arc<SpeakerEmbedder>) -> Self {
+        NeuralDiarizer { embedder }
+    }
+}
+
+impl Diarizer for NeuralDiarizer {
+    fn name(&self) -> &'static str {
+        "neural"
+    }
+    
+    fn speaker_vectors(&self, samples: &[f32], sample_rate: u32, ranges: &[Range<usize>]) -> Result<SpeakerVectors, String> {
+        let mut vectors = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match self.embedder.embed(&samples[range.clone()], sample_rate) {
+                Ok(embedding) => vectors.push(embedding),
+                Err(e) => {
+                    // Fall back to MFCC features for the whole recording
+                    eprintln!("Speaker embedding failed, falling back to MFCC features: {}", e);
+                    return HeuristicDiarizer.speaker_vectors(samples, sample_rate, ranges);
+                }
+            }
+        }
+        
+        Ok(SpeakerVectors { kind: VectorKind::Embedding, vectors })
+    }
+}
+
+// Maximum number of recent recordings kept for speaker enrollment
+const MAX_RECENT_RECORDINGS: usize = 50;
+
+// Speaker vectors of diarized recordings
+#[derive(Default)]
+struct Recordings {
+    // Recordings since the current session started
+    session: Vec<SessionChunk>,
+    // Recent recordings, labelled with the ids shown in the UI, for enrollment
+    recent: VecDeque<SessionChunk>,
+}
+
+// Wraps a backend, labels enrolled voices, and remembers the speaker vectors so
+// the whole session can be re-clustered when recording stops
+struct SessionDiarizer {
+    inner: Arc<dyn Diarizer>,
+    job_id: u64,
+    profiles: Vec<SpeakerProfile>,
+    recordings: Arc<Mutex<Recordings>>,
+}
+
+impl Diarizer for SessionDiarizer {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    
+    fn speaker_vectors(&self, samples: &[f32], sample_rate: u32, ranges: &[Range<usize>]) -> Result<SpeakerVectors, String> {
+        self.inner.speaker_vectors(samples, sample_rate, ranges)
+    }
+    
+    fn diarize(&self, samples: &[f32], sample_rate: u32, options: &ClusteringOptions) -> Result<DiarizationResult, String> {
+        let ranges = speech_ranges(samples, sample_rate);
+        let vectors = self.speaker_vectors(samples, sample_rate, &ranges)?;
+        let mut result = cluster_vectors(&vectors, &range_times(&ranges, sample_rate), options, &self.profiles);
+        smooth_speaker_assignments(&mut result.segments);
+        
+        if !result.segments.is_empty() {
+            let chunk = SessionChunk {
+                job_id: self.job_id,
+                speaker_ids: result.segments.iter().map(|segment| segment.speaker_id.clone()).collect(),
+                vectors,
+            };
+            
+            let mut recordings = self.recordings.lock().unwrap();
+            recordings.session.push(chunk.clone());
+            recordings.recent.push_back(chunk);
+            if recordings.recent.len() > MAX_RECENT_RECORDINGS {
+                recordings.recent.pop_front();
+            }
+        }
+        
+        Ok(result)
+    }
+}
+
+// Speaker vectors from one diarized recording of a session
+#[derive(Clone)]
+struct SessionChunk {
+    job_id: u64,
+    // Speaker of each vector within its own recording
+    speaker_ids: Vec<String>,
+    vectors: SpeakerVectors,
+}
+
+// Maps a speaker id within one recording to its id across the whole session
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerRelabel {
+    pub job_id: u64,
+    pub from: String,
+    pub to: String,
+}
+
+// Result of re-clustering a session, sent with the `diarization-session-complete` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionClustering {
+    pub speakers: Vec<Speaker>,
+    pub relabels: Vec<SpeakerRelabel>,
+}
+
+// Re-cluster every recording of a session together so speakers keep the same id
+// across recordings
+fn recluster_session(chunks: &[SessionChunk], options: &ClusteringOptions, profiles: &[SpeakerProfile]) -> Option<SessionClustering> {
+    // Vectors of different kinds can't be compared, so use the kind of the latest recording
+    let latest = chunks.last()?;
+    let kind = latest.vectors.kind;
+    let dims = latest.vectors.vectors.first()?.len();
+    let chunks: Vec<&SessionChunk> = chunks.iter()
+        .filter(|chunk| chunk.vectors.kind == kind && chunk.vectors.vectors.iter().all(|v| v.len() == dims))
+        .collect();
+    
+    if chunks.len() < 2 {
+        return None;
+    }
+    
+    let all = SpeakerVectors {
+        kind,
+        vectors: chunks.iter().flat_map(|chunk| chunk.vectors.vectors.iter().cloned()).collect(),
+    };
+    
+    // Segment times aren't used once clustered
+    let times = vec![(0.0, 0.0); all.vectors.len()];
+    let options = ClusteringOptions { mode: ClusteringMode::Agglomerative, ..options.clone() };
+    let mut result = cluster_vectors(&all, &times, &options, profiles);
+    
+    // Smooth each recording on its own; neighbouring segments in different recordings aren't contiguous
+    let mut start = 0;
+    for chunk in &chunks {
+        let end = (start + chunk.vectors.vectors.len()).min(result.segments.len());
+        smooth_speaker_assignments(&mut result.segments[start..end]);
+        start = end;
+    }
+    
+    let mut speakers: Vec<Speaker> = Vec::new();
+    let mut relabels = Vec::new();
+    let mut session_ids = result.segments.iter().map(|segment| &segment.speaker_id);
+    
+    for chunk in chunks {
+        // Each local speaker becomes the session speaker most of its segments were assigned to
+        let mut votes: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+        for local in &chunk.speaker_ids {
+            let Some(session) = session_ids.next() else { break };
+            *votes.entry(local.as_str()).or_default().entry(session.as_str()).or_default() += 1;
+        }
+        
+        for (local, counts) in votes {
+            let Some((session, _)) = counts.into_iter().max_by_key(|(_, count)| *count) else { continue };
+            
+            if !speakers.iter().any(|s| s.id == session) {
+                speakers.push(Speaker {
+                    id: session.to_string(),
+                    name: result.names.get(session).cloned().unwrap_or_else(|| session.to_string()),
+                    confidence: result.confidence.get(session).copied(),
+                });
+            }
+            
+            relabels.push(SpeakerRelabel {
+                job_id: chunk.job_id,
+                from: local.to_string(),
+                to: session.to_string(),
+            });
+        }
+    }
+    
+    speakers.sort_by(|a, b| a.id.cmp(&b.id));
+    
+    Some(SessionClustering { speakers, relabels })
+}
+
+// Which diarization backend to use
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiarizerBackend {
+    // Neural if a speaker embedding model is available, heuristic otherwise
+    Auto,
+    Heuristic,
+    Neural,
+}
+
+// Description of a backend for the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct DiarizerBackendInfo {
+    pub id: DiarizerBackend,
+    pub name: String,
+    pub available: bool,
+    pub selected: bool,
+}
+
+// Runtime selection of the diarization backend, held in Tauri managed state
+pub struct DiarizationEngine {
+    embeddings: SpeakerEmbeddings,
+    selected: Mutex<DiarizerBackend>,
+    options: Mutex<ClusteringOptions>,
+    profiles: SpeakerStore,
+    recordings: Arc<Mutex<Recordings>>,
+}
+
+impl DiarizationEngine {
+    pub fn new(embeddings: SpeakerEmbeddings, profiles: SpeakerStore) -> Self {
+        DiarizationEngine {
+            embeddings,
+            selected: Mutex::new(DiarizerBackend::Auto),
+            options: Mutex::new(ClusteringOptions::default()),
+            profiles,
+            recordings: Arc::new(Mutex::new(Recordings::default())),
+        }
+    }
+    
+    // The backend that will be used for the next diarization
+    pub fn diarizer(&self) -> Arc<dyn Diarizer> {
+        let selected = *self.selected.lock().unwrap();
+        
+        match (selected, self.embeddings.get()) {
+            (DiarizerBackend::Auto | DiarizerBackend::Neural, Some(embedder)) => Arc::new(NeuralDiarizer::new(embedder)),
+            _ => Arc::new(HeuristicDiarizer),
+        }
+    }
+    
+    // The backend for a recording that is part of the current session
+    pub fn session_diarizer(&self, job_id: u64) -> Arc<dyn Diarizer> {
+        Arc::new(SessionDiarizer {
+            inner: self.diarizer(),
+            job_id,
+            profiles: self.profiles.list(),
+            recordings: self.recordings.clone(),
+        })
+    }
+    
+    // End the current session, re-clustering all of its recordings together.
+    // Returns None if there was nothing to re-cluster.
+    pub fn finish_session(&self) -> Option<SessionClustering> {
+        let mut recordings = self.recordings.lock().unwrap();
+        let chunks = std::mem::take(&mut recordings.session);
+        let clustering = recluster_session(&chunks, &self.clustering_options(), &self.profiles.list())?;
+        
+        // Keep the recent recordings in step with the ids now shown in the UI
+        for chunk in recordings.recent.iter_mut() {
+            let relabels: Vec<&SpeakerRelabel> = clustering.relabels.iter()
+                .filter(|relabel| relabel.job_id == chunk.job_id)
+                .collect();
+            for speaker_id in chunk.speaker_ids.iter_mut() {
+                if let Some(relabel) = relabels.iter().find(|relabel| relabel.from == *speaker_id) {
+                    *speaker_id = relabel.to.clone();
+                }
+            }
+        }
+        
+        Some(clustering)
+    }
+    
+    // Name a speaker. Their voice from the recording `job_id` is enrolled, so that
+    // future sessions recognise it; enrolled speakers are renamed.
+    pub fn enroll_speaker(&self, job_id: Option<u64>, speaker_id: &str, name: &str) -> Result<Speaker, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Speaker name cannot be empty".to_string());
+        }
+        
+        let mut recordings = self.recordings.lock().unwrap();
+        let chunk = job_id.and_then(|job_id| recordings.recent.iter_mut().find(|chunk| chunk.job_id == job_id));
+        let existing = Some(speaker_id).filter(|id| SpeakerStore::is_profile_id(id));
+        
+        let profile = match chunk {
+            Some(chunk) => {
+                let vectors: Vec<Vec<f32>> = chunk.speaker_ids.iter()
+                    .zip(&chunk.vectors.vectors)
+                    .filter(|(id, _)| id.as_str() == speaker_id)
+                    .map(|(_, vector)| vector.clone())
+                    .collect();
+                let profile = self.profiles.enroll(existing, name, chunk.vectors.kind, &vectors)?;
+                
+                // Later enrollments from this recording add to the same profile
+                for id in chunk.speaker_ids.iter_mut().filter(|id| id.as_str() == speaker_id) {
+                    *id = profile.id.clone();
+                }
+                profile
+            }
+            None => match existing {
+                Some(id) => self.profiles.rename(id, name)?,
+                None => return Err(format!("No recent recording of {} to enroll", speaker_id)),
+            },
+        };
+        
+        Ok(Speaker {
+            id: profile.id,
+            name: profile.name,
+            confidence: None,
+        })
+    }
+    
+    pub fn speaker_profiles(&self) -> Vec<Speaker> {
+        self.profiles.list()
+            .into_iter()
+            .map(|profile| Speaker {
+                id: profile.id,
+                name: profile.name,
+                confidence: None,
+            })
+            .collect()
+    }
+    
+    pub fn delete_speaker_profile(&self, profile_id: &str) -> Result<(), String> {
+        self.profiles.delete(profile_id)
+    }
+    
+    // "advanced" when the neural backend is in use, "fallback" otherwise
+    pub fn status(&self) -> &'static str {
+        match self.diarizer().name() {
+            "neural" => "advanced",
+            _ => "fallback",
+        }
+    }
+    
+    pub fn backends(&self) -> Vec<DiarizerBackendInfo> {
+        let selected = *self.selected.lock().unwrap();
+        let neural_available = self.embeddings.get().is_some();
+        
+        [
+            (DiarizerBackend::Auto, "Automatic", true),
+            (DiarizerBackend::Heuristic, "Basic (MFCC)", true),
+            (DiarizerBackend::Neural, "Neural speaker embeddings", neural_available),
+        ]
+        .into_iter()
+        .map(|(id, name, available)| DiarizerBackendInfo {
+            id,
+            name: name.to_string(),
+            available,
+            selected: id == selected,
+        })
+        .collect()
+    }
+    
+    pub fn select(&self, backend: DiarizerBackend) -> Result<(), String> {
+        if backend == DiarizerBackend::Neural && self.embeddings.get().is_none() {
+            return Err(format!(
+                "No speaker embedding model available. Place {} in the models folder and build with the `onnx` feature.",
+                EMBEDDING_MODEL_FILE
+            ));
+        }
+        
+        *self.selected.lock().unwrap() = backend;
+        println!("Diarization backend set to {:?}", backend);
+        Ok(())
+    }
+    
+    pub fn clustering_options(&self) -> ClusteringOptions {
+        self.options.lock().unwrap().clone()
+    }
+    
+    pub fn set_clustering_options(&self, options: ClusteringOptions) -> Result<(), String> {
+        if options.min_speakers == Some(0) || options.max_speakers == Some(0) {
+            return Err("Speaker counts must be at least 1".to_string());
+        }
+        
+        if let (Some(min), Some(max)) = (options.min_speakers, options.max_speakers) {
+            if min > max {
+                return Err(format!("Minimum speakers ({}) is greater than maximum speakers ({})", min, max));
+            }
+        }
+        
+        println!("Clustering options set to {:?}", options);
+        *self.options.lock().unwrap() = options;
+        Ok(())
+    }
+}
+
+// Split audio into fixed-size segments, skipping silent ones
+fn speech_ranges(samples: &[f32], sample_rate: u32) -> Vec<Range<usize>> {
     // For simplicity, we'll use fixed-size segments
     let segment_duration = 1.5; // 1.5 seconds per segment (shorter for better resolution)
     let samples_per_segment = (segment_duration * sample_rate as f32) as usize;
     
-    let mut segment_times = Vec::new();
-    let mut feature_vectors = Vec::new();
-    
     // Skip segments with very low energy (silence)
     let silence_threshold = 0.01;
     
+    let mut ranges = Vec::new();
+    
     for i in (0..samples.len()).step_by(samples_per_segment) {
         if i + samples_per_segment > samples.len() {
             break;
         }
         
-        let start_time = i as f32 / sample_rate as f32;
-        let end_time = (i + samples_per_segment) as f32 / sample_rate as f32;
-        
         // Extract segment samples
         let segment_samples = &samples[i..i+samples_per_segment];
         
@@ -215,95 +951,83 @@ pub fn diarize(samples: &[f32], sample_rate: u32) -> DiarizationResult {
             continue; // Skip silent segments
         }
         
-        segment_times.push((start_time, end_time));
-        
-        // Extract features for this segment
-        let features = extract_features(segment_samples, sample_rate);
-        
-        // Use average of features for the segment
-        if !features.is_empty() {
-            let mut avg_features = vec![0.0; features[0].len()];
-            for feature in &features {
-                for (i, &value) in feature.iter().enumerate() {
-                    avg_features[i] += value;
-                }
-            }
-            
-            for value in &mut avg_features {
-                *value /= features.len() as f32;
-            }
-            
-            feature_vectors.push(avg_features);
-        }
+        ranges.push(i..i + samples_per_segment);
     }
     
-    // Step 2: Cluster the features to identify speakers
-    let speaker_segments = cluster_features(&feature_vectors, &segment_times);
-    
-    DiarizationResult {
-        segments: speaker_segments,
-    }
+    ranges
+}
+
+// Start and end time in seconds of each range of samples
+fn range_times(ranges: &[Range<usize>], sample_rate: u32) -> Vec<(f32, f32)> {
+    ranges.iter()
+        .map(|range| (range.start as f32 / sample_rate as f32, range.end as f32 / sample_rate as f32))
+        .collect()
 }
 
-// Function to combine diarization results with transcription
+// Function to combine diarization results with transcription.
+// Each transcript segment is split wherever the speaker changes between words.
 pub fn combine_with_transcription(
     diarization: &DiarizationResult,
-    transcription: &str,
-    timestamps: &[(f32, f32)]
-) -> String {
-    let mut result = String::new();
-    
-    // Split transcription into words
-    let words: Vec<&str> = transcription.split_whitespace().collect();
-    
-    // Assume timestamps correspond to words
-    // In a real implementation, you would need to align them properly
-    if words.len() != timestamps.len() {
-        return format!("Error: Mismatch between words ({}) and timestamps ({})", 
-                      words.len(), timestamps.len());
-    }
-    
-    // Create a vector of (speaker, word) pairs
-    let mut speaker_word_pairs = Vec::new();
-    
-    // Assign speaker to each word based on timestamp
-    for (i, &word) in words.iter().enumerate() {
-        let word_time = timestamps[i].0;
-        
-        // Find which speaker segment this word belongs to
-        let mut speaker = String::from("Unknown");
-        for segment in &diarization.segments {
-            if word_time >= segment.start_time && word_time <= segment.end_time {
-                speaker = segment.speaker_id.clone();
-                break;
-            }
-        }
-        
-        // Add to result
-        speaker_word_pairs.push((speaker, word.to_string()));
-    }
-    
-    // Join segments with appropriate formatting
-    let mut current_speaker = String::new();
-    let mut current_text = String::new();
+    segments: &[Segment]
+) -> (Vec<Segment>, Vec<Speaker>) {
+    let mut diarized_segments: Vec<Segment> = Vec::new();
+    let mut speakers: Vec<Speaker> = Vec::new();
     
-    for (speaker, word) in speaker_word_pairs {
-        if speaker != current_speaker {
-            // New speaker, start a new paragraph
-            if !current_text.is_empty() {
-                result.push_str(&format!("{}: {}\n\n", current_speaker, current_text.trim()));
-                current_text.clear();
+    for segment in segments {
+        let mut current_speaker: Option<String> = None;
+        let mut current_words: Vec<Word> = Vec::new();
+        
+        for word in &segment.words {
+            let speaker = speaker_at(diarization, (word.start + word.end) / 2.0);
+            
+            // New speaker, start a new segment
+            if current_speaker.as_ref() != Some(&speaker) && !current_words.is_empty() {
+                let id = diarized_segments.len();
+                diarized_segments.push(Segment::from_words(id, std::mem::take(&mut current_words), current_speaker.take()));
+            }
+            
+            if !speakers.iter().any(|s| s.id == speaker) {
+                speakers.push(Speaker {
+                    id: speaker.clone(),
+                    name: diarization.names.get(&speaker).cloned().unwrap_or_else(|| speaker.clone()),
+                    confidence: diarization.confidence.get(&speaker).copied(),
+                });
             }
-            current_speaker = speaker;
+            
+            current_speaker = Some(speaker);
+            current_words.push(word.clone());
         }
         
-        current_text.push_str(&format!("{} ", word));
+        // Add the last part of the segment
+        if !current_words.is_empty() {
+            let id = diarized_segments.len();
+            diarized_segments.push(Segment::from_words(id, current_words, current_speaker));
+        }
     }
     
-    // Add the last segment
-    if !current_text.is_empty() {
-        result.push_str(&format!("{}: {}\n", current_speaker, current_text.trim()));
+    (diarized_segments, speakers)
+}
+
+// Find the speaker talking at a point in time
+fn speaker_at(diarization: &DiarizationResult, time: f32) -> String {
+    // Find which speaker segment this time belongs to, falling back to the
+    // closest segment for words spoken during skipped (quiet) segments
+    diarization.segments.iter()
+        .find(|segment| time >= segment.start_time && time <= segment.end_time)
+        .or_else(|| diarization.segments.iter().min_by(|a, b| {
+            segment_distance(a, time)
+                .partial_cmp(&segment_distance(b, time))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }))
+        .map(|segment| segment.speaker_id.clone())
+        .unwrap_or_else(|| String::from("Unknown"))
+}
+
+// Distance in seconds from a point in time to a speaker segment
+fn segment_distance(segment: &SpeakerSegment, time: f32) -> f32 {
+    if time < segment.start_time {
+        segment.start_time - time
+    } else {
+        (time - segment.end_time).max(0.0)
     }
-    
-    result
-} 
\ No newline at end of file
+}
@@ -0,0 +1,190 @@
+// Neural speaker embeddings (ECAPA-TDNN / TitaNet style) from a local ONNX model.
+// Only available when built with the `onnx` cargo feature; ONNX Runtime is loaded
+// dynamically, so the shared library must be installed or pointed to by ORT_DYLIB_PATH.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// File name of the speaker embedding model inside the models directory
+pub const EMBEDDING_MODEL_FILE: &str = "speaker-embedding.onnx";
+
+// Sample rate speaker embedding models are trained on
+#[cfg(feature = "onnx")]
+const EMBEDDING_SAMPLE_RATE: u32 = 16000;
+
+// Number of mel bands used by fbank-input models
+#[cfg(feature = "onnx")]
+const FBANK_BANDS: usize = 80;
+
+// How the model expects its input
+#[cfg(feature = "onnx")]
+#[derive(Debug, Clone, Copy)]
+enum InputLayout {
+    // Raw waveform, [batch, samples]
+    Waveform,
+    // Log-mel filterbank, [batch, frames, bands]
+    FbankFramesFirst,
+    // Log-mel filterbank, [batch, bands, frames]
+    FbankBandsFirst,
+}
+
+// A loaded speaker embedding model
+#[cfg(feature = "onnx")]
+pub struct SpeakerEmbedder {
+    session: std::sync::Mutex<ort::session::Session>,
+    layout: InputLayout,
+    fbank: crate::features::FeatureExtractor,
+}
+
+#[cfg(not(feature = "onnx"))]
+pub struct SpeakerEmbedder {
+    _private: (),
+}
+
+#[cfg(feature = "onnx")]
+impl SpeakerEmbedder {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        use ort::value::ValueType;
+
+        let session = ort::session::Session::builder()
+            .and_then(|builder| builder.commit_from_file(path))
+            .map_err(|e| format!("Failed to load speaker embedding model {}: {}", path.display(), e))?;
+
+        // Work out the input layout from the declared shape of the first input
+        let layout = match session.inputs.first().map(|input| &input.input_type) {
+            Some(ValueType::Tensor { shape, .. }) if shape.len() == 3 && shape[2] == FBANK_BANDS as i64 => InputLayout::FbankFramesFirst,
+            Some(ValueType::Tensor { shape, .. }) if shape.len() == 3 && shape[1] == FBANK_BANDS as i64 => InputLayout::FbankBandsFirst,
+            Some(ValueType::Tensor { shape, .. }) if shape.len() == 2 => InputLayout::Waveform,
+            other => return Err(format!("Unsupported speaker embedding model input: {:?}", other)),
+        };
+
+        println!("Loaded speaker embedding model {} ({:?} input)", path.display(), layout);
+
+        Ok(SpeakerEmbedder {
+            session: std::sync::Mutex::new(session),
+            layout,
+            fbank: crate::features::FeatureExtractor::new(EMBEDDING_SAMPLE_RATE, FBANK_BANDS, FBANK_BANDS),
+        })
+    }
+
+    // Compute an L2-normalized embedding for a stretch of 16kHz speech
+    pub fn embed(&self, samples: &[f32], sample_rate: u32) -> Result<Vec<f32>, String> {
+        use ort::value::Tensor;
+
+        if sample_rate != EMBEDDING_SAMPLE_RATE {
+            return Err(format!("Speaker embeddings need {}Hz audio, got {}Hz", EMBEDDING_SAMPLE_RATE, sample_rate));
+        }
+
+        let input = match self.layout {
+            InputLayout::Waveform => Tensor::from_array(([1usize, samples.len()], samples.to_vec())),
+            InputLayout::FbankFramesFirst | InputLayout::FbankBandsFirst => {
+                let frames = self.normalized_fbank(samples);
+                if frames.is_empty() {
+                    return Err("Segment too short for a speaker embedding".to_string());
+                }
+
+                let n_frames = frames.len();
+                if let InputLayout::FbankFramesFirst = self.layout {
+                    Tensor::from_array(([1usize, n_frames, FBANK_BANDS], frames.concat()))
+                } else {
+                    let transposed = (0..FBANK_BANDS)
+                        .flat_map(|band| frames.iter().map(move |frame| frame[band]))
+                        .collect::<Vec<f32>>();
+                    Tensor::from_array(([1usize, FBANK_BANDS, n_frames], transposed))
+                }
+            }
+        }
+        .map_err(|e| format!("Failed to build embedding input: {}", e))?;
+
+        let mut session = self.session.lock().unwrap();
+        let outputs = session
+            .run(ort::inputs![input])
+            .map_err(|e| format!("Speaker embedding inference failed: {}", e))?;
+
+        let (_, embedding) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| format!("Unexpected speaker embedding output: {}", e))?;
+
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt().max(1e-9);
+        Ok(embedding.iter().map(|v| v / norm).collect())
+    }
+
+    // Log-mel filterbank with per-band mean normalization, as used by Kaldi-style front-ends
+    fn normalized_fbank(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let mut frames = self.fbank.log_mel(samples);
+        if frames.is_empty() {
+            return frames;
+        }
+
+        let count = frames.len() as f32;
+        for band in 0..FBANK_BANDS {
+            let mean = frames.iter().map(|frame| frame[band]).sum::<f32>() / count;
+            for frame in frames.iter_mut() {
+                frame[band] -= mean;
+            }
+        }
+
+        frames
+    }
+}
+
+#[cfg(not(feature = "onnx"))]
+impl SpeakerEmbedder {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Err(format!(
+            "Cannot load {}: this build does not include ONNX support (enable the `onnx` feature)",
+            path.display()
+        ))
+    }
+
+    pub fn embed(&self, _samples: &[f32], _sample_rate: u32) -> Result<Vec<f32>, String> {
+        Err("This build does not include ONNX support".to_string())
+    }
+}
+
+// Lazily loaded speaker embedding model, held in Tauri managed state.
+// Until it loads, each use looks for it again, so a model installed while the app
+// runs is picked up. A model that failed to load is only tried again once its file changes.
+pub struct SpeakerEmbeddings {
+    model_path: PathBuf,
+    embedder: Mutex<Option<Arc<SpeakerEmbedder>>>,
+    // Modification time of the file that last failed to load
+    failed: Mutex<Option<SystemTime>>,
+}
+
+impl SpeakerEmbeddings {
+    pub fn new(model_path: PathBuf) -> Self {
+        SpeakerEmbeddings {
+            model_path,
+            embedder: Mutex::new(None),
+            failed: Mutex::new(None),
+        }
+    }
+
+    // The embedding model, if one is installed and could be loaded
+    pub fn get(&self) -> Option<Arc<SpeakerEmbedder>> {
+        let mut embedder = self.embedder.lock().unwrap();
+        if embedder.is_some() {
+            return embedder.clone();
+        }
+
+        let modified = self.model_path.metadata().ok().filter(|metadata| metadata.is_file())?.modified().ok();
+        let mut failed = self.failed.lock().unwrap();
+        if modified.is_some() && *failed == modified {
+            return None;
+        }
+
+        match SpeakerEmbedder::load(&self.model_path) {
+            Ok(loaded) => {
+                *failed = None;
+                *embedder = Some(Arc::new(loaded));
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                *failed = modified;
+            }
+        }
+        embedder.clone()
+    }
+}
@@ -0,0 +1,202 @@
+// Log-mel and MFCC feature extraction for speaker diarization
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+// Frame length and hop in seconds (25ms / 10ms, the usual speech front-end)
+const FRAME_SEC: f32 = 0.025;
+const HOP_SEC: f32 = 0.010;
+
+// Pre-emphasis coefficient applied before framing
+const PRE_EMPHASIS: f32 = 0.97;
+
+// Lowest mel filter edge in Hz
+const MIN_FREQ: f32 = 20.0;
+
+// Floor applied before taking the log of filterbank energies
+const LOG_FLOOR: f32 = 1e-10;
+
+// Computes log-mel filterbank energies and MFCCs from mono audio
+pub struct FeatureExtractor {
+    frame_len: usize,
+    hop_len: usize,
+    n_fft: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    // One triangular filter per mel band, over the `n_fft / 2 + 1` power spectrum bins
+    mel_filters: Vec<Vec<f32>>,
+    // Orthonormal DCT-II basis, one row per cepstral coefficient
+    dct: Vec<Vec<f32>>,
+}
+
+impl FeatureExtractor {
+    pub fn new(sample_rate: u32, n_mels: usize, n_mfcc: usize) -> Self {
+        let frame_len = (sample_rate as f32 * FRAME_SEC) as usize;
+        let hop_len = (sample_rate as f32 * HOP_SEC) as usize;
+        let n_fft = frame_len.next_power_of_two();
+
+        let fft = FftPlanner::new().plan_fft_forward(n_fft);
+
+        // Hamming window
+        let window = (0..frame_len)
+            .map(|i| 0.54 - 0.46 * (2.0 * std::f32::consts::PI * i as f32 / (frame_len - 1) as f32).cos())
+            .collect();
+
+        let max_freq = sample_rate as f32 / 2.0;
+
+        FeatureExtractor {
+            frame_len,
+            hop_len,
+            n_fft,
+            fft,
+            window,
+            mel_filters: mel_filterbank(n_mels, n_fft, sample_rate, MIN_FREQ, max_freq),
+            dct: dct_basis(n_mfcc.min(n_mels), n_mels),
+        }
+    }
+
+    // Log mel filterbank energies, one vector per 10ms frame
+    pub fn log_mel(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        if samples.len() < self.frame_len {
+            return Vec::new();
+        }
+
+        // Pre-emphasis boosts high frequencies that carry speaker detail
+        let mut emphasized = Vec::with_capacity(samples.len());
+        emphasized.push(samples[0]);
+        for i in 1..samples.len() {
+            emphasized.push(samples[i] - PRE_EMPHASIS * samples[i - 1]);
+        }
+
+        let mut buffer = vec![Complex::new(0.0f32, 0.0); self.n_fft];
+        let mut frames = Vec::new();
+
+        for start in (0..=emphasized.len() - self.frame_len).step_by(self.hop_len) {
+            // Window the frame and zero-pad to the FFT size
+            for (i, value) in buffer.iter_mut().enumerate() {
+                let sample = if i < self.frame_len {
+                    emphasized[start + i] * self.window[i]
+                } else {
+                    0.0
+                };
+                *value = Complex::new(sample, 0.0);
+            }
+
+            self.fft.process(&mut buffer);
+
+            // Power spectrum of the non-negative frequencies
+            let power: Vec<f32> = buffer[..self.n_fft / 2 + 1]
+                .iter()
+                .map(|c| c.norm_sqr() / self.n_fft as f32)
+                .collect();
+
+            let energies = self.mel_filters
+                .iter()
+                .map(|filter| {
+                    let energy: f32 = filter.iter().zip(&power).map(|(w, p)| w * p).sum();
+                    energy.max(LOG_FLOOR).ln()
+                })
+                .collect();
+
+            frames.push(energies);
+        }
+
+        frames
+    }
+
+    // Mel-frequency cepstral coefficients, one vector per 10ms frame
+    pub fn mfcc(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        self.log_mel(samples)
+            .iter()
+            .map(|log_mel| {
+                self.dct
+                    .iter()
+                    .map(|basis| basis.iter().zip(log_mel).map(|(b, e)| b * e).sum())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Fixed-size description of a stretch of speech: the mean and standard deviation
+    // of every MFCC except c0, which only tracks loudness
+    pub fn mfcc_statistics(&self, samples: &[f32]) -> Option<Vec<f32>> {
+        let frames = self.mfcc(samples);
+        if frames.is_empty() {
+            return None;
+        }
+
+        let dims = frames[0].len();
+        let count = frames.len() as f32;
+        let mut stats = Vec::with_capacity((dims - 1) * 2);
+
+        for d in 1..dims {
+            let mean = frames.iter().map(|f| f[d]).sum::<f32>() / count;
+            stats.push(mean);
+        }
+
+        for d in 1..dims {
+            let mean = stats[d - 1];
+            let variance = frames.iter().map(|f| (f[d] - mean).powi(2)).sum::<f32>() / count;
+            stats.push(variance.sqrt());
+        }
+
+        Some(stats)
+    }
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+// Triangular filters spaced evenly on the mel scale
+fn mel_filterbank(n_mels: usize, n_fft: usize, sample_rate: u32, min_freq: f32, max_freq: f32) -> Vec<Vec<f32>> {
+    let n_bins = n_fft / 2 + 1;
+    let min_mel = hz_to_mel(min_freq);
+    let max_mel = hz_to_mel(max_freq);
+
+    // n_mels + 2 edges: each filter spans from the previous to the next center
+    let edges: Vec<f32> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(min_mel + (max_mel - min_mel) * i as f32 / (n_mels + 1) as f32))
+        .collect();
+
+    let bin_freq = |bin: usize| bin as f32 * sample_rate as f32 / n_fft as f32;
+
+    (0..n_mels)
+        .map(|m| {
+            let (left, center, right) = (edges[m], edges[m + 1], edges[m + 2]);
+            (0..n_bins)
+                .map(|bin| {
+                    let freq = bin_freq(bin);
+                    if freq <= left || freq >= right {
+                        0.0
+                    } else if freq <= center {
+                        (freq - left) / (center - left)
+                    } else {
+                        (right - freq) / (right - center)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Orthonormal DCT-II basis
+fn dct_basis(n_coeffs: usize, n_inputs: usize) -> Vec<Vec<f32>> {
+    (0..n_coeffs)
+        .map(|k| {
+            let scale = if k == 0 {
+                (1.0 / n_inputs as f32).sqrt()
+            } else {
+                (2.0 / n_inputs as f32).sqrt()
+            };
+            (0..n_inputs)
+                .map(|n| scale * (std::f32::consts::PI * k as f32 * (n as f32 + 0.5) / n_inputs as f32).cos())
+                .collect()
+        })
+        .collect()
+}
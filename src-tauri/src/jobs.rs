@@ -0,0 +1,195 @@
+// Background transcription jobs with progress reporting and cancellation
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager};
+use crate::diarize::DiarizationEngine;
+use crate::recorder::{self, RecordingLink};
+use crate::transcribe::{self, InferenceControl, ModelRegistry};
+use crate::transcript::{SourceTag, Transcript};
+
+// A recording waiting to be transcribed
+struct Job {
+    id: u64,
+    samples: Vec<f32>,
+    diarize: bool,
+    // Last recording of a diarization session; its speakers are re-clustered once it is done
+    end_session: bool,
+    // Tag for the transcript's segments when the recording is one side of a call
+    source: Option<SourceTag>,
+    // Part of a saved recording the samples come from
+    recording: Option<RecordingLink>,
+    cancelled: Arc<AtomicBool>,
+}
+
+// Payload for the `transcription-started` and `transcription-cancelled` events
+#[derive(Debug, Clone, Serialize)]
+pub struct JobEvent {
+    pub job_id: u64,
+}
+
+// Payload for the `transcription-queued` event
+#[derive(Debug, Clone, Serialize)]
+pub struct JobQueuedEvent {
+    pub job_id: u64,
+    pub diarize: bool,
+    pub source: Option<SourceTag>,
+}
+
+// Payload for the `transcription-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct JobProgressEvent {
+    pub job_id: u64,
+    pub progress: i32,
+}
+
+// Payload for the `transcription-complete` event
+#[derive(Debug, Clone, Serialize)]
+pub struct JobCompleteEvent {
+    pub job_id: u64,
+    pub transcript: Transcript,
+    pub recording: Option<RecordingLink>,
+}
+
+// Payload for the `transcription-failed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFailedEvent {
+    pub job_id: u64,
+    pub error: String,
+}
+
+// Queue of transcription jobs processed in order by a single worker thread.
+// Commands only enqueue work and return the job id; results arrive as events.
+pub struct TranscriptionQueue {
+    sender: Mutex<Sender<Job>>,
+    next_id: AtomicU64,
+    // Cancellation flags for jobs that are queued or running
+    active: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>,
+}
+
+impl TranscriptionQueue {
+    pub fn new(app: AppHandle) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let active = Arc::new(Mutex::new(HashMap::new()));
+
+        let worker_active = active.clone();
+        thread::spawn(move || run_worker(app, receiver, worker_active));
+
+        TranscriptionQueue {
+            sender: Mutex::new(sender),
+            next_id: AtomicU64::new(1),
+            active,
+        }
+    }
+
+    // Queue 16kHz samples for transcription and return the job id
+    pub fn enqueue(
+        &self,
+        app: &AppHandle,
+        samples: Vec<f32>,
+        diarize: bool,
+        end_session: bool,
+        source: Option<SourceTag>,
+        recording: Option<RecordingLink>,
+    ) -> Result<u64, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        self.active.lock().unwrap().insert(id, cancelled.clone());
+
+        self.sender
+            .lock()
+            .unwrap()
+            .send(Job { id, samples, diarize, end_session, source, recording, cancelled })
+            .map_err(|_| "Transcription worker is not running".to_string())?;
+
+        emit(app, "transcription-queued", JobQueuedEvent { job_id: id, diarize, source });
+        Ok(id)
+    }
+
+    // Cancel a queued or running job
+    pub fn cancel(&self, job_id: u64) -> Result<(), String> {
+        match self.active.lock().unwrap().get(&job_id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            None => Err(format!("No active transcription job with id {}", job_id)),
+        }
+    }
+}
+
+fn run_worker(app: AppHandle, receiver: Receiver<Job>, active: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>) {
+    for job in receiver {
+        if job.cancelled.load(Ordering::SeqCst) {
+            println!("Skipping cancelled transcription job {}", job.id);
+            emit(&app, "transcription-cancelled", JobEvent { job_id: job.id });
+        } else {
+            run_job(&app, &job);
+        }
+
+        if job.end_session {
+            if let Some(clustering) = app.state::<DiarizationEngine>().finish_session() {
+                emit(&app, "diarization-session-complete", clustering);
+            }
+        }
+
+        active.lock().unwrap().remove(&job.id);
+    }
+}
+
+fn run_job(app: &AppHandle, job: &Job) {
+    println!("Starting transcription job {}", job.id);
+    emit(app, "transcription-started", JobEvent { job_id: job.id });
+
+    let job_id = job.id;
+    let progress_app = app.clone();
+    let on_progress = move |progress: i32| {
+        emit(&progress_app, "transcription-progress", JobProgressEvent { job_id, progress });
+    };
+
+    let control = InferenceControl {
+        on_progress: &on_progress,
+        cancelled: &job.cancelled,
+    };
+
+    let models = app.state::<ModelRegistry>();
+    let result = if job.diarize {
+        let engine = app.state::<DiarizationEngine>();
+        let diarizer = engine.session_diarizer(job.id);
+        let options = engine.clustering_options();
+        transcribe::transcribe_with_diarization(&models, &job.samples, diarizer.as_ref(), &options, Some(&control))
+    } else {
+        transcribe::transcribe(&models, &job.samples, Some(&control))
+    };
+
+    match result {
+        Ok(mut transcript) => {
+            for segment in &mut transcript.segments {
+                segment.source = job.source;
+            }
+            if let Some(link) = &job.recording {
+                recorder::add_segments(link, &transcript.segments);
+            }
+            emit(app, "transcription-complete", JobCompleteEvent { job_id, transcript, recording: job.recording.clone() });
+        }
+        Err(_) if job.cancelled.load(Ordering::SeqCst) => {
+            println!("Transcription job {} cancelled", job_id);
+            emit(app, "transcription-cancelled", JobEvent { job_id });
+        }
+        Err(error) => {
+            eprintln!("Transcription job {} failed: {}", job_id, error);
+            emit(app, "transcription-failed", JobFailedEvent { job_id, error });
+        }
+    }
+}
+
+fn emit<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    if let Err(e) = app.emit(event, payload) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
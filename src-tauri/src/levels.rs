@@ -0,0 +1,164 @@
+// Live input level metering. Each session's recording thread measures peak and RMS
+// per channel over ~50ms blocks; a separate thread publishes them as events.
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter};
+use crate::audio::{OverrunCounters, SessionId};
+
+// Length of a metering block
+const BLOCK_MS: u32 = 50;
+
+// Level reported for digital silence
+const MIN_DB: f32 = -100.0;
+
+// An input whose peak stays below this for DEAD_INPUT_SECS is reported as dead
+const DEAD_INPUT_DB: f32 = -70.0;
+const DEAD_INPUT_SECS: u32 = 3;
+
+// Peaks at or above this are reported as clipping
+const CLIP_LEVEL: f32 = 0.999;
+
+// Blocks waiting to be published; further blocks are dropped if the UI falls behind
+const MAX_PENDING_BLOCKS: usize = 4;
+
+static EVENTS: OnceCell<Mutex<SyncSender<AudioLevels>>> = OnceCell::new();
+
+// Level of one channel over a block
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelLevel {
+    // Linear levels, 0 to 1
+    pub peak: f32,
+    pub rms: f32,
+    // The same levels in dBFS
+    pub peak_db: f32,
+    pub rms_db: f32,
+}
+
+// Payload for the `audio-level` event
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioLevels {
+    pub session_id: SessionId,
+    pub channels: Vec<ChannelLevel>,
+    pub clipping: bool,
+    // No signal on any channel for DEAD_INPUT_SECS
+    pub dead_input: bool,
+    // Audio dropped so far because capture processing fell behind
+    pub dropped_frames: u64,
+    pub overruns: u64,
+}
+
+// Start the publishing thread; called once from the app setup
+pub fn init(app: AppHandle) {
+    let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_BLOCKS);
+    let _ = EVENTS.set(Mutex::new(sender));
+
+    thread::spawn(move || run_events(app, receiver));
+}
+
+fn run_events(app: AppHandle, receiver: Receiver<AudioLevels>) {
+    for levels in receiver {
+        if let Err(e) = app.emit("audio-level", levels) {
+            eprintln!("Failed to emit audio-level: {}", e);
+        }
+    }
+}
+
+// Accumulates interleaved samples into per-channel levels. Fed from the recording thread.
+pub struct LevelMeter {
+    session_id: SessionId,
+    channels: usize,
+    block_frames: usize,
+    frames: usize,
+    peak: Vec<f32>,
+    sum_squares: Vec<f64>,
+    // Consecutive frames below DEAD_INPUT_DB
+    quiet_frames: usize,
+    dead_after_frames: usize,
+    overruns: Arc<OverrunCounters>,
+}
+
+impl LevelMeter {
+    pub fn new(session_id: SessionId, sample_rate: u32, channels: u16, overruns: Arc<OverrunCounters>) -> Self {
+        let channels = channels.max(1) as usize;
+
+        LevelMeter {
+            session_id,
+            channels,
+            block_frames: (sample_rate * BLOCK_MS / 1000).max(1) as usize,
+            frames: 0,
+            peak: vec![0.0; channels],
+            sum_squares: vec![0.0; channels],
+            quiet_frames: 0,
+            dead_after_frames: (sample_rate * DEAD_INPUT_SECS) as usize,
+            overruns,
+        }
+    }
+
+    // Measure interleaved samples, publishing a level event for each completed block
+    pub fn process(&mut self, data: &[f32]) {
+        for frame in data.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.peak[channel] = self.peak[channel].max(sample.abs());
+                self.sum_squares[channel] += (sample as f64) * (sample as f64);
+            }
+
+            self.frames += 1;
+            if self.frames == self.block_frames {
+                self.publish();
+            }
+        }
+    }
+
+    fn publish(&mut self) {
+        let channels: Vec<ChannelLevel> = self.peak
+            .iter()
+            .zip(&self.sum_squares)
+            .map(|(&peak, &sum_squares)| {
+                let rms = (sum_squares / self.frames as f64).sqrt() as f32;
+                ChannelLevel {
+                    peak,
+                    rms,
+                    peak_db: to_db(peak),
+                    rms_db: to_db(rms),
+                }
+            })
+            .collect();
+
+        let loudest_db = channels.iter().map(|level| level.peak_db).fold(MIN_DB, f32::max);
+        if loudest_db < DEAD_INPUT_DB {
+            self.quiet_frames += self.frames;
+        } else {
+            self.quiet_frames = 0;
+        }
+
+        let levels = AudioLevels {
+            session_id: self.session_id,
+            clipping: channels.iter().any(|level| level.peak >= CLIP_LEVEL),
+            dead_input: self.quiet_frames >= self.dead_after_frames,
+            dropped_frames: self.overruns.dropped_frames(),
+            overruns: self.overruns.overruns(),
+            channels,
+        };
+
+        // Never block the recording thread; drop the block if the publisher is behind
+        if let Some(sender) = EVENTS.get() {
+            let _ = sender.lock().unwrap().try_send(levels);
+        }
+
+        self.frames = 0;
+        self.peak.iter_mut().for_each(|peak| *peak = 0.0);
+        self.sum_squares.iter_mut().for_each(|sum| *sum = 0.0);
+    }
+}
+
+fn to_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(MIN_DB)
+    } else {
+        MIN_DB
+    }
+}
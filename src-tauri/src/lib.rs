@@ -1,121 +1,647 @@
 mod audio;
+mod channels;
 mod transcribe;
 mod diarize;
+mod streaming;
+mod models;
+mod transcript;
+mod jobs;
+mod features;
+mod embedding;
+mod speakers;
+mod vad;
+mod levels;
+mod resample;
+mod pulse;
+mod loopback;
+mod settings;
+mod devices;
+mod spool;
+mod recorder;
 
-use serde::Serialize;
+use audio::{CaptureFormat, CaptureSessionInfo, CaptureSessions, SessionId};
+use channels::ChannelOptions;
+use devices::{DeviceLossPolicy, InputDevice};
+use diarize::{ClusteringOptions, DiarizationEngine, DiarizerBackend, DiarizerBackendInfo};
+use embedding::{SpeakerEmbeddings, EMBEDDING_MODEL_FILE};
+use jobs::TranscriptionQueue;
+use loopback::{DeviceMatch, DeviceRule};
+use models::{ModelInfo, ModelManager};
+use pulse::MonitorSource;
+use recorder::{AudioFormat, RecordingOptions, SavedRecording};
+use settings::SettingsStore;
+use speakers::SpeakerStore;
+use spool::RecoverableRecording;
+use tauri::{AppHandle, Manager, State};
+use transcribe::ModelRegistry;
+use transcript::{SourceTag, Speaker};
 
-// Define a struct for device info
-#[derive(Serialize)]
-struct DeviceInfo {
-    id: String,
-    name: String,
+// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-// Define a struct for speaker info
-#[derive(Serialize)]
-struct SpeakerInfo {
-    id: String,
-    name: String,
+// Open a capture session on the selected device and start it
+#[tauri::command(async)]
+fn start_recording(captures: State<'_, CaptureSessions>) -> Result<CaptureSessionInfo, String> {
+    let session = captures.open(false, None);
+    session.start()?;
+    Ok(session.info())
 }
 
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+#[tauri::command(async)]
+fn stop_recording(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    session_id: SessionId,
+) -> Result<u64, String> {
+    finish_recording(&app, &captures, &queue, session_id, false)
+}
+
+#[tauri::command(async)]
+fn stop_recording_with_diarization(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    session_id: SessionId,
+) -> Result<u64, String> {
+    finish_recording(&app, &captures, &queue, session_id, true)
+}
+
+// Open a capture session on the system audio device and start it
+#[tauri::command(async)]
+fn start_recording_system(captures: State<'_, CaptureSessions>) -> Result<CaptureSessionInfo, String> {
+    let session = captures.open(true, None);
+    session.start()?;
+    Ok(session.info())
+}
+
+// Capture the selected microphone and the system audio as two sessions, tagged "me" and
+// "remote". Each is stopped and transcribed on its own with the commands above.
+#[tauri::command(async)]
+fn start_dual_recording(captures: State<'_, CaptureSessions>) -> Result<Vec<CaptureSessionInfo>, String> {
+    let me = captures.open(false, Some(SourceTag::Me));
+    let remote = captures.open(true, Some(SourceTag::Remote));
+    
+    me.start()?;
+    if let Err(e) = remote.start() {
+        let _ = me.stop();
+        return Err(e);
+    }
+    
+    Ok(vec![me.info(), remote.info()])
+}
+
+#[tauri::command(async)]
+fn stop_recording_system(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    session_id: SessionId,
+) -> Result<u64, String> {
+    finish_recording(&app, &captures, &queue, session_id, false)
+}
+
+#[tauri::command(async)]
+fn stop_recording_system_with_diarization(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    session_id: SessionId,
+) -> Result<u64, String> {
+    finish_recording(&app, &captures, &queue, session_id, true)
+}
+
+// Pause every session of the current recording. The streams stay open, nothing is
+// captured or transcribed until they resume, and the gap is kept in each session's pauses.
+#[tauri::command(async)]
+fn pause_recording(captures: State<'_, CaptureSessions>, session_ids: Vec<SessionId>) -> Result<Vec<CaptureSessionInfo>, String> {
+    let sessions = session_ids
+        .into_iter()
+        .map(|id| captures.get(id))
+        .collect::<Result<Vec<_>, String>>()?;
+    
+    for session in &sessions {
+        session.pause()?;
+    }
+    Ok(sessions.iter().map(|session| session.info()).collect())
+}
+
+#[tauri::command(async)]
+fn resume_recording(captures: State<'_, CaptureSessions>, session_ids: Vec<SessionId>) -> Result<Vec<CaptureSessionInfo>, String> {
+    let sessions = session_ids
+        .into_iter()
+        .map(|id| captures.get(id))
+        .collect::<Result<Vec<_>, String>>()?;
+    
+    for session in &sessions {
+        session.resume()?;
+    }
+    Ok(sessions.iter().map(|session| session.info()).collect())
+}
+
+// Stop the capture and queue whatever hasn't already been committed by the segmenter.
+// This is the last recording of the session.
+fn finish_recording(
+    app: &AppHandle,
+    captures: &CaptureSessions,
+    queue: &TranscriptionQueue,
+    session_id: SessionId,
+    diarize: bool,
+) -> Result<u64, String> {
+    let session = captures.get(session_id)?;
+    let from = vad::stop_segmenting(session_id);
+    session.stop()?;
+    
+    // A long recording is queued in pieces. Only the last piece of a diarized recording
+    // ends a diarization session; in a dual capture the other side may still be adding to it.
+    let mut job_id = 0;
+    session.process_chunks(from, |audio_data, at, last| {
+        let recording = session.recording().map(|target| target.link(at));
+        job_id = queue.enqueue(app, audio_data, diarize, diarize && last, session.tag(), recording)?;
+        Ok(())
+    })?;
+    recorder::save(app.clone(), session);
+    Ok(job_id)
+}
+
+// Capture session lifecycle: open, start, pause, resume, stop and close.
+// The recording commands above combine these with transcription.
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+fn open_capture(captures: State<'_, CaptureSessions>) -> CaptureSessionInfo {
+    captures.open_default().info()
+}
+
+#[tauri::command(async)]
+fn start_capture(captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<CaptureFormat, String> {
+    captures.get(session_id)?.start()
 }
 
 #[tauri::command]
-fn start_recording() -> Result<(), String> {
-    audio::start_capture()
+fn pause_capture(captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<(), String> {
+    captures.get(session_id)?.pause()
 }
 
 #[tauri::command]
-fn stop_recording() -> Result<String, String> {
-    let audio_data = audio::stop_capture()?;
-    transcribe::transcribe(&audio_data)
+fn resume_capture(captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<(), String> {
+    captures.get(session_id)?.resume()
 }
 
+// Stop the stream without transcribing; the audio is kept until the session is closed
 #[tauri::command]
-fn stop_recording_with_diarization() -> Result<String, String> {
-    let audio_data = audio::stop_capture()?;
-    transcribe::transcribe_with_diarization(&audio_data)
+fn stop_capture(app: AppHandle, captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<(), String> {
+    vad::stop_segmenting(session_id);
+    let session = captures.get(session_id)?;
+    session.stop()?;
+    recorder::save(app, session);
+    Ok(())
 }
 
 #[tauri::command]
-fn start_recording_system() -> Result<(), String> {
-    audio::start_system_capture()
+fn close_capture(captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<(), String> {
+    vad::stop_segmenting(session_id);
+    captures.close(session_id)
 }
 
 #[tauri::command]
-fn stop_recording_system() -> Result<String, String> {
-    let audio_data = audio::stop_capture()?;
-    transcribe::transcribe(&audio_data)
+fn list_capture_sessions(captures: State<'_, CaptureSessions>) -> Vec<CaptureSessionInfo> {
+    captures.list()
 }
 
+// Transcribe each utterance as soon as voice activity detection sees it end
 #[tauri::command]
-fn stop_recording_system_with_diarization() -> Result<String, String> {
-    let audio_data = audio::stop_capture()?;
-    transcribe::transcribe_with_diarization(&audio_data)
+fn start_segmenting(captures: State<'_, CaptureSessions>, session_id: SessionId, diarize: bool) -> Result<(), String> {
+    let session = captures.get(session_id)?;
+    vad::start_segmenting(&session, diarize)
 }
 
 #[tauri::command]
-fn set_audio_source(is_system: bool) {
-    audio::set_audio_source(is_system);
+fn cancel_transcription(queue: State<'_, TranscriptionQueue>, job_id: u64) -> Result<(), String> {
+    queue.cancel(job_id)
 }
 
 #[tauri::command]
-fn is_system_audio_available() -> bool {
-    audio::is_system_audio_available()
+fn start_streaming(app: tauri::AppHandle, captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<(), String> {
+    streaming::start_streaming(app, captures.get(session_id)?)
 }
 
 #[tauri::command]
-fn get_input_devices() -> Result<Vec<DeviceInfo>, String> {
-    let devices = audio::get_input_devices()?;
+fn stop_streaming(app: AppHandle, captures: State<'_, CaptureSessions>, session_id: SessionId) -> Result<(), String> {
+    let session = captures.get(session_id)?;
+    streaming::stop_streaming(&session)?;
+    recorder::save(app, session);
+    Ok(())
+}
+
+#[tauri::command(async)]
+fn load_model(manager: State<'_, ModelManager>, models: State<'_, ModelRegistry>, model_path: String) -> Result<String, String> {
+    // Same header and checksum checks as models picked from the models directory
+    let path = std::path::Path::new(&model_path);
+    manager.verify(path)?;
     
-    // Convert to DeviceInfo structs
-    let device_infos = devices
-        .into_iter()
-        .map(|(id, name)| DeviceInfo { id, name })
-        .collect();
+    let model = models.load(path)?;
+    Ok(model.path.display().to_string())
+}
+
+#[tauri::command]
+fn get_active_model(models: State<'_, ModelRegistry>) -> Option<String> {
+    models.active_path().map(|path| path.display().to_string())
+}
+
+#[tauri::command]
+fn list_models(manager: State<'_, ModelManager>, models: State<'_, ModelRegistry>) -> Result<Vec<ModelInfo>, String> {
+    manager.list_models(models.active_path().as_deref())
+}
+
+#[tauri::command(async)]
+fn set_active_model(
+    manager: State<'_, ModelManager>,
+    models: State<'_, ModelRegistry>,
+    model_id: String,
+) -> Result<ModelInfo, String> {
+    // Validate the file before replacing the active model
+    let path = manager.resolve(&model_id)?;
+    let mut info = manager.verify(&path)?;
     
-    Ok(device_infos)
+    models.load(&path)?;
+    info.is_active = true;
+    
+    Ok(info)
+}
+
+#[tauri::command(async)]
+fn verify_model(manager: State<'_, ModelManager>, model_id: String) -> Result<ModelInfo, String> {
+    let path = manager.resolve(&model_id)?;
+    manager.verify(&path)
 }
 
 #[tauri::command]
-fn set_input_device(device_id: Option<String>) {
-    audio::set_selected_device(device_id);
+fn get_models_dir(manager: State<'_, ModelManager>) -> String {
+    manager.models_dir().display().to_string()
 }
 
 #[tauri::command]
-fn rename_speaker(speaker_id: String, new_name: String) -> Result<SpeakerInfo, String> {
-    // This is a placeholder function that would actually update the speaker names
-    // in a real implementation. For now, we'll just log the request and return the updated info.
-    println!("Renaming speaker {} to {}", speaker_id, new_name);
+fn set_audio_source(captures: State<'_, CaptureSessions>, is_system: bool) {
+    captures.set_audio_source(is_system);
+}
+
+// Asks the sound server on Linux, which can take a moment
+#[tauri::command(async)]
+fn is_system_audio_available(captures: State<'_, CaptureSessions>) -> bool {
+    audio::is_system_audio_available(&captures.loopback_rules())
+}
+
+#[tauri::command]
+fn get_loopback_rules(captures: State<'_, CaptureSessions>) -> Vec<DeviceRule> {
+    captures.loopback_rules()
+}
+
+// Rules are in priority order; the first one matching an input device picks it
+#[tauri::command]
+fn set_loopback_rules(
+    settings: State<'_, SettingsStore>,
+    captures: State<'_, CaptureSessions>,
+    rules: Vec<DeviceRule>,
+) -> Result<(), String> {
+    loopback::validate(&rules)?;
+    settings.update(|settings| settings.loopback_rules = rules.clone())?;
+    captures.set_loopback_rules(rules);
+    Ok(())
+}
+
+#[tauri::command]
+fn reset_loopback_rules(
+    settings: State<'_, SettingsStore>,
+    captures: State<'_, CaptureSessions>,
+) -> Result<Vec<DeviceRule>, String> {
+    let rules = loopback::default_rules();
+    settings.update(|settings| settings.loopback_rules = rules.clone())?;
+    captures.set_loopback_rules(rules.clone());
+    Ok(rules)
+}
+
+// Which device system audio would be captured from with the current settings, and why
+#[tauri::command(async)]
+fn match_system_device(captures: State<'_, CaptureSessions>) -> Result<DeviceMatch, String> {
+    audio::match_system_device(captures.selected_monitor().as_deref(), &captures.loopback_rules())
+}
+
+// Outputs whose audio can be captured directly (PulseAudio / PipeWire on Linux)
+#[tauri::command(async)]
+fn list_output_monitors() -> Result<Vec<MonitorSource>, String> {
+    pulse::list_monitor_sources()
+}
+
+#[tauri::command]
+fn set_output_monitor(captures: State<'_, CaptureSessions>, monitor_name: Option<String>) {
+    captures.set_selected_monitor(monitor_name);
+}
+
+#[tauri::command]
+fn get_channel_options(captures: State<'_, CaptureSessions>) -> ChannelOptions {
+    captures.channel_options()
+}
+
+#[tauri::command]
+fn set_channel_options(captures: State<'_, CaptureSessions>, options: ChannelOptions) {
+    captures.set_channel_options(options);
+}
+
+// Transcribe one channel of a recording on its own, e.g. one side of a stereo call recording.
+// A long recording is queued in pieces; the id of the last is returned.
+#[tauri::command(async)]
+fn transcribe_channel(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    session_id: SessionId,
+    channel: usize,
+) -> Result<u64, String> {
+    let session = captures.get(session_id)?;
+    let mut job_id = 0;
+    session.channel_chunks(channel, |samples, _, _| {
+        job_id = queue.enqueue(&app, samples, false, false, session.tag(), None)?;
+        Ok(())
+    })?;
+    Ok(job_id)
+}
+
+// Recordings left behind by sessions that never stopped, e.g. because the app crashed
+#[tauri::command(async)]
+fn list_recoverable_recordings(captures: State<'_, CaptureSessions>) -> Vec<RecoverableRecording> {
+    spool::list_recoverable(captures.spool_dir())
+}
+
+// Queue a recovered recording for transcription, in pieces like a stopped session.
+// The spool file is removed once queued.
+#[tauri::command(async)]
+fn recover_recording(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    recording_id: String,
+    diarize: bool,
+) -> Result<u64, String> {
+    let mut recording = spool::open_recoverable(captures.spool_dir(), &recording_id)?;
+    let source = recording.source();
+    
+    let mut job_id = 0;
+    audio::recovered_chunks(&mut recording, |samples, _, last| {
+        job_id = queue.enqueue(&app, samples, diarize, diarize && last, source, None)?;
+        Ok(())
+    })?;
     
-    // Return the updated speaker info
-    Ok(SpeakerInfo {
-        id: speaker_id,
-        name: new_name,
-    })
+    drop(recording);
+    spool::discard_recoverable(captures.spool_dir(), &recording_id)?;
+    Ok(job_id)
+}
+
+#[tauri::command]
+fn discard_recording(captures: State<'_, CaptureSessions>, recording_id: String) -> Result<(), String> {
+    spool::discard_recoverable(captures.spool_dir(), &recording_id)
+}
+
+#[tauri::command]
+fn get_recording_options(captures: State<'_, CaptureSessions>) -> RecordingOptions {
+    captures.recording_options()
+}
+
+// Whether sessions save their audio once they stop, and in which format
+#[tauri::command]
+fn set_recording_options(
+    settings: State<'_, SettingsStore>,
+    captures: State<'_, CaptureSessions>,
+    options: RecordingOptions,
+) -> Result<(), String> {
+    recorder::validate(&options)?;
+    settings.update(|settings| settings.recording = options.clone())?;
+    captures.set_recording_options(options);
+    Ok(())
+}
+
+// Ogg Opus is only available in builds with the `opus` feature
+#[tauri::command]
+fn list_recording_formats() -> Vec<AudioFormat> {
+    recorder::supported_formats()
+}
+
+#[tauri::command(async)]
+fn list_saved_recordings(captures: State<'_, CaptureSessions>) -> Vec<SavedRecording> {
+    recorder::list(captures.recordings_dir())
+}
+
+#[tauri::command]
+fn delete_saved_recording(captures: State<'_, CaptureSessions>, recording_id: String) -> Result<(), String> {
+    recorder::delete(captures.recordings_dir(), &recording_id)
+}
+
+// A span of a saved recording's 16kHz audio as WAV bytes, for playback
+#[tauri::command(async)]
+fn get_recording_clip(
+    captures: State<'_, CaptureSessions>,
+    recording_id: String,
+    start: f32,
+    end: f32,
+) -> Result<tauri::ipc::Response, String> {
+    let wav = recorder::clip(captures.recordings_dir(), &recording_id, start, end)?;
+    Ok(tauri::ipc::Response::new(wav))
+}
+
+// Transcribe a span of a saved recording again. Its new segments replace the old ones in the recording.
+#[tauri::command(async)]
+fn retranscribe_recording(
+    app: AppHandle,
+    captures: State<'_, CaptureSessions>,
+    queue: State<'_, TranscriptionQueue>,
+    recording_id: String,
+    start: f32,
+    end: f32,
+    diarize: bool,
+) -> Result<u64, String> {
+    let samples = recorder::read_processed(captures.recordings_dir(), &recording_id, start, end)?;
+    let link = recorder::relink(captures.recordings_dir(), &recording_id, start, end)?;
+    queue.enqueue(&app, samples, diarize, diarize, link.source(), Some(link))
+}
+
+// Device ids stay the same across restarts and reconnects
+#[tauri::command(async)]
+fn get_input_devices() -> Result<Vec<InputDevice>, String> {
+    devices::list_input_devices()
+}
+
+#[tauri::command]
+fn set_input_device(captures: State<'_, CaptureSessions>, device_id: Option<String>) {
+    captures.set_selected_device(device_id);
+}
+
+#[tauri::command]
+fn get_device_loss_policy(captures: State<'_, CaptureSessions>) -> DeviceLossPolicy {
+    captures.device_loss()
+}
+
+// Whether a capture stops or waits for its device when the device disappears
+#[tauri::command]
+fn set_device_loss_policy(
+    settings: State<'_, SettingsStore>,
+    captures: State<'_, CaptureSessions>,
+    policy: DeviceLossPolicy,
+) -> Result<(), String> {
+    settings.update(|settings| settings.device_loss = policy)?;
+    captures.set_device_loss(policy);
+    Ok(())
+}
+
+// Loading the embedding model on first use can take a moment
+#[tauri::command(async)]
+fn get_diarization_model_status(engine: State<'_, DiarizationEngine>) -> String {
+    engine.status().to_string()
+}
+
+#[tauri::command(async)]
+fn list_diarization_backends(engine: State<'_, DiarizationEngine>) -> Vec<DiarizerBackendInfo> {
+    engine.backends()
+}
+
+#[tauri::command(async)]
+fn set_diarization_backend(engine: State<'_, DiarizationEngine>, backend: DiarizerBackend) -> Result<String, String> {
+    engine.select(backend)?;
+    Ok(engine.status().to_string())
+}
+
+#[tauri::command]
+fn get_clustering_options(engine: State<'_, DiarizationEngine>) -> ClusteringOptions {
+    engine.clustering_options()
+}
+
+#[tauri::command]
+fn set_clustering_options(engine: State<'_, DiarizationEngine>, options: ClusteringOptions) -> Result<(), String> {
+    engine.set_clustering_options(options)
+}
+
+// Renaming a speaker enrolls their voice from the given recording, so it is
+// recognised in future sessions. The returned speaker has the enrolled profile id.
+#[tauri::command]
+fn rename_speaker(
+    engine: State<'_, DiarizationEngine>,
+    speaker_id: String,
+    new_name: String,
+    job_id: Option<u64>,
+) -> Result<Speaker, String> {
+    println!("Renaming speaker {} to {}", speaker_id, new_name);
+    engine.enroll_speaker(job_id, &speaker_id, &new_name)
+}
+
+#[tauri::command]
+fn list_speaker_profiles(engine: State<'_, DiarizationEngine>) -> Vec<Speaker> {
+    engine.speaker_profiles()
+}
+
+#[tauri::command]
+fn delete_speaker_profile(engine: State<'_, DiarizationEngine>, profile_id: String) -> Result<(), String> {
+    engine.delete_speaker_profile(&profile_id)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            // Models live in the app data directory
+            let models_dir = app.path().app_data_dir()?.join("models");
+            vad::init(app.handle().clone(), models_dir.clone());
+            levels::init(app.handle().clone());
+            devices::init(app.handle().clone());
+            let settings = SettingsStore::load(app.path().app_data_dir()?.join("settings.json"));
+            let saved = settings.get();
+            // Session audio is spooled here; recordings cut short by a crash are kept for recovery
+            let spool_dir = app.path().app_data_dir()?.join("spool");
+            spool::clean_up(&spool_dir);
+            // Saved session audio, when recordings are on
+            let recordings_dir = app.path().app_data_dir()?.join("recordings");
+            app.manage(CaptureSessions::new(
+                spool_dir,
+                recordings_dir,
+                saved.loopback_rules,
+                saved.device_loss,
+                saved.recording,
+            ));
+            app.manage(settings);
+            let embeddings = SpeakerEmbeddings::new(models_dir.join(EMBEDDING_MODEL_FILE));
+            let profiles = SpeakerStore::load(app.path().app_data_dir()?.join("speakers.json"));
+            app.manage(DiarizationEngine::new(embeddings, profiles));
+            let manager = ModelManager::new(models_dir);
+            
+            app.manage(ModelRegistry::new(manager.default_model()));
+            app.manage(manager);
+            
+            // Transcription runs on a background worker, not on the command thread
+            app.manage(TranscriptionQueue::new(app.handle().clone()));
+            
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             start_recording,
+            open_capture,
+            start_capture,
+            pause_capture,
+            resume_capture,
+            stop_capture,
+            close_capture,
+            list_capture_sessions,
             stop_recording,
             stop_recording_with_diarization,
             start_recording_system,
+            start_dual_recording,
+            pause_recording,
+            resume_recording,
             stop_recording_system,
             stop_recording_system_with_diarization,
+            start_segmenting,
+            cancel_transcription,
+            start_streaming,
+            stop_streaming,
+            load_model,
+            get_active_model,
+            list_models,
+            set_active_model,
+            verify_model,
+            get_models_dir,
             set_audio_source,
             is_system_audio_available,
+            get_loopback_rules,
+            set_loopback_rules,
+            reset_loopback_rules,
+            match_system_device,
+            list_output_monitors,
+            set_output_monitor,
+            get_channel_options,
+            set_channel_options,
+            transcribe_channel,
+            list_recoverable_recordings,
+            recover_recording,
+            discard_recording,
+            get_recording_options,
+            set_recording_options,
+            list_recording_formats,
+            list_saved_recordings,
+            delete_saved_recording,
+            get_recording_clip,
+            retranscribe_recording,
             get_input_devices,
             set_input_device,
-            rename_speaker
+            get_device_loss_policy,
+            set_device_loss_policy,
+            get_diarization_model_status,
+            list_diarization_backends,
+            set_diarization_backend,
+            get_clustering_options,
+            set_clustering_options,
+            rename_speaker,
+            list_speaker_profiles,
+            delete_speaker_profile
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,113 @@
+// Recognising the virtual loopback device (BlackHole, VB-Audio Cable, ...) that
+// carries system audio. Rules are tried in priority order; the first rule that
+// matches any input device wins.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    // The device name contains the pattern
+    Substring,
+    // The device name is the pattern
+    Exact,
+    // The device name matches the pattern as a regular expression
+    Regex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceRule {
+    pub kind: MatchKind,
+    pub pattern: String,
+}
+
+// The device a rule picked for system audio, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceMatch {
+    pub device: String,
+    // Position of the matching rule in priority order; None when no rule decided
+    pub rule_index: Option<usize>,
+    pub rule: Option<DeviceRule>,
+    pub reason: String,
+}
+
+// The names the app has always looked for
+pub fn default_rules() -> Vec<DeviceRule> {
+    ["BlackHole", "VB-Audio", "CABLE Output", "Soundflower", "Virtual Audio", "Loopback"]
+        .into_iter()
+        .map(|pattern| DeviceRule {
+            kind: MatchKind::Substring,
+            pattern: pattern.to_string(),
+        })
+        .collect()
+}
+
+// Check that every rule can be used, so a bad pattern is reported when it is saved
+pub fn validate(rules: &[DeviceRule]) -> Result<(), String> {
+    for (index, rule) in rules.iter().enumerate() {
+        if rule.pattern.is_empty() {
+            return Err(format!("Rule {} has an empty pattern", index + 1));
+        }
+        if rule.kind == MatchKind::Regex {
+            Regex::new(&rule.pattern)
+                .map_err(|e| format!("Rule {} is not a valid regular expression: {}", index + 1, e))?;
+        }
+    }
+    Ok(())
+}
+
+// Find the device to use among `names`. Returns the index of the device along
+// with an explanation of which rule picked it.
+pub fn find_match(rules: &[DeviceRule], names: &[String]) -> Option<(usize, DeviceMatch)> {
+    for (rule_index, rule) in rules.iter().enumerate() {
+        let matcher = match Matcher::new(rule) {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                eprintln!("Skipping loopback rule {}: {}", rule_index + 1, e);
+                continue;
+            }
+        };
+
+        if let Some(device_index) = names.iter().position(|name| matcher.matches(name)) {
+            let device = names[device_index].clone();
+            let reason = match rule.kind {
+                MatchKind::Substring => format!("Name contains \"{}\" (rule {})", rule.pattern, rule_index + 1),
+                MatchKind::Exact => format!("Name is \"{}\" (rule {})", rule.pattern, rule_index + 1),
+                MatchKind::Regex => format!("Name matches /{}/ (rule {})", rule.pattern, rule_index + 1),
+            };
+
+            return Some((device_index, DeviceMatch {
+                device,
+                rule_index: Some(rule_index),
+                rule: Some(rule.clone()),
+                reason,
+            }));
+        }
+    }
+    None
+}
+
+enum Matcher<'a> {
+    Substring(&'a str),
+    Exact(&'a str),
+    Regex(Regex),
+}
+
+impl<'a> Matcher<'a> {
+    fn new(rule: &'a DeviceRule) -> Result<Self, regex::Error> {
+        Ok(match rule.kind {
+            MatchKind::Substring => Matcher::Substring(&rule.pattern),
+            MatchKind::Exact => Matcher::Exact(&rule.pattern),
+            MatchKind::Regex => Matcher::Regex(Regex::new(&rule.pattern)?),
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Matcher::Substring(pattern) => name.contains(pattern),
+            Matcher::Exact(pattern) => name == *pattern,
+            Matcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+// Discovery and validation of Whisper ggml models in the app data directory
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// "ggml" magic at the start of every whisper.cpp model file
+const GGML_MAGIC: u32 = 0x67676d6c;
+
+// Quantized models encode a format version in the ftype as `version * 1000 + ftype`
+const GGML_QNT_VERSION_FACTOR: i32 = 1000;
+
+// Model file loaded when nothing else is available
+const DEFAULT_MODEL_FILE: &str = "ggml-small.bin";
+
+// Legacy model location from before the models directory existed
+const LEGACY_MODEL_PATH: &str = "whisper-small.bin";
+
+// Published SHA-1 checksums for the official whisper.cpp models
+const KNOWN_CHECKSUMS: &[(&str, &str)] = &[
+    ("ggml-tiny.bin", "bd577a113a864445d4c299885e0cb97d4ba92b5f"),
+    ("ggml-tiny.en.bin", "c78c86eb1a8faa21b369bcd33207cc90d64ae9df"),
+    ("ggml-base.bin", "465707469ff3a37a2b9b8d8f89f2f99de7299dac"),
+    ("ggml-base.en.bin", "137c40403d78fd54d454da0f9bd998f78703390c"),
+    ("ggml-small.bin", "55356645c2b361a969dfd0ef2c5a50d530afd8d5"),
+    ("ggml-small.en.bin", "db8a495a91d927739e50b3fc1cc4c6b8f6c2d022"),
+    ("ggml-medium.bin", "fd9727b6e1217c2f614f9b698455c4ffd82463b4"),
+    ("ggml-medium.en.bin", "8c30f0e44ce9560643ebd10bbe50cd20eafd3723"),
+    ("ggml-large-v1.bin", "b1caaf735c4cc1429223d5a74f0f4d0b9b59a299"),
+    ("ggml-large-v2.bin", "0f4c8e34f21cf1a914c59d8b3ce882345ad349d6"),
+    ("ggml-large-v3.bin", "ad82bf6a9043ceed055076d0fd39f5f186ff8062"),
+];
+
+// Hyperparameters stored in the ggml header
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelHeader {
+    pub n_vocab: i32,
+    pub n_audio_ctx: i32,
+    pub n_audio_state: i32,
+    pub n_audio_head: i32,
+    pub n_audio_layer: i32,
+    pub n_text_ctx: i32,
+    pub n_text_state: i32,
+    pub n_text_head: i32,
+    pub n_text_layer: i32,
+    pub n_mels: i32,
+    pub ftype: i32,
+}
+
+impl ModelHeader {
+    // Model size inferred from the number of encoder layers
+    pub fn model_type(&self) -> &'static str {
+        match self.n_audio_layer {
+            4 => "tiny",
+            6 => "base",
+            12 => "small",
+            24 => "medium",
+            32 => "large",
+            _ => "unknown",
+        }
+    }
+
+    // English-only models have a smaller vocabulary
+    pub fn is_multilingual(&self) -> bool {
+        self.n_vocab >= 51865
+    }
+
+    // Weight type, with the quantization format version stripped
+    pub fn quantization(&self) -> &'static str {
+        match self.ftype % GGML_QNT_VERSION_FACTOR {
+            0 => "f32",
+            1 => "f16",
+            2 => "q4_0",
+            3 => "q4_1",
+            7 => "q8_0",
+            8 => "q5_0",
+            9 => "q5_1",
+            10 => "q2_k",
+            11 => "q3_k",
+            12 => "q4_k",
+            13 => "q5_k",
+            14 => "q6_k",
+            _ => "unknown",
+        }
+    }
+}
+
+// Result of checking a model file against its reference checksum
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrity {
+    // Checksum matches the published value
+    Verified,
+    // Checksum does not match; the file is corrupt or truncated
+    Mismatch,
+    // There is no reference checksum for this file
+    Unknown,
+    // The checksum has not been computed yet
+    Unchecked,
+}
+
+// Information about a model file reported to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub model_type: String,
+    pub multilingual: bool,
+    pub quantization: String,
+    pub n_mels: i32,
+    pub integrity: Integrity,
+    pub is_active: bool,
+}
+
+// Cached checksum for a file, invalidated when its size or mtime changes
+struct CachedChecksum {
+    size: u64,
+    modified: Option<SystemTime>,
+    sha1: String,
+}
+
+// Scans the models directory and validates model files
+pub struct ModelManager {
+    models_dir: PathBuf,
+    checksums: Mutex<HashMap<PathBuf, CachedChecksum>>,
+}
+
+impl ModelManager {
+    pub fn new(models_dir: PathBuf) -> Self {
+        if let Err(e) = fs::create_dir_all(&models_dir) {
+            eprintln!("Failed to create models directory {}: {}", models_dir.display(), e);
+        }
+
+        ModelManager {
+            models_dir,
+            checksums: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn models_dir(&self) -> &Path {
+        &self.models_dir
+    }
+
+    // List every readable ggml model in the models directory.
+    // Checksums are only reported if they have already been computed.
+    pub fn list_models(&self, active_path: Option<&Path>) -> Result<Vec<ModelInfo>, String> {
+        let mut models = Vec::new();
+
+        for path in self.model_files()? {
+            let header = match read_header(&path) {
+                Ok(header) => header,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            let integrity = match self.cached_checksum(&path) {
+                Some(sha1) => compare_checksum(&path, &sha1),
+                None => Integrity::Unchecked,
+            };
+
+            models.push(model_info(&path, &header, integrity, active_path));
+        }
+
+        Ok(models)
+    }
+
+    // Resolve a model id (its file name) to a path inside the models directory
+    pub fn resolve(&self, id: &str) -> Result<PathBuf, String> {
+        // Only accept plain file names so ids cannot escape the models directory
+        if Path::new(id).file_name().map(|name| name != id).unwrap_or(true) {
+            return Err(format!("Invalid model id: {}", id));
+        }
+
+        let path = self.models_dir.join(id);
+        if !path.is_file() {
+            return Err(format!("Model '{}' not found in {}", id, self.models_dir.display()));
+        }
+
+        Ok(path)
+    }
+
+    // Validate the header and checksum of a model file
+    pub fn verify(&self, path: &Path) -> Result<ModelInfo, String> {
+        let header = read_header(path)?;
+        let sha1 = self.checksum(path)?;
+        let integrity = compare_checksum(path, &sha1);
+
+        if integrity == Integrity::Mismatch {
+            return Err(format!(
+                "Checksum mismatch for {} (got {}). The file is corrupt or incomplete; please download it again.",
+                path.display(),
+                sha1
+            ));
+        }
+
+        Ok(model_info(path, &header, integrity, None))
+    }
+
+    // Model to load when none has been selected: the default model if present,
+    // otherwise the first valid model in the directory, otherwise the legacy location
+    pub fn default_model(&self) -> PathBuf {
+        let default_path = self.models_dir.join(DEFAULT_MODEL_FILE);
+        if default_path.is_file() {
+            return default_path;
+        }
+
+        if let Ok(files) = self.model_files() {
+            if let Some(path) = files.into_iter().find(|path| read_header(path).is_ok()) {
+                return path;
+            }
+        }
+
+        let legacy_path = PathBuf::from(LEGACY_MODEL_PATH);
+        if legacy_path.is_file() {
+            return legacy_path;
+        }
+
+        default_path
+    }
+
+    // All `.bin` files in the models directory, sorted by name
+    fn model_files(&self) -> Result<Vec<PathBuf>, String> {
+        let entries = fs::read_dir(&self.models_dir)
+            .map_err(|e| format!("Failed to read models directory {}: {}", self.models_dir.display(), e))?;
+
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().map(|ext| ext == "bin").unwrap_or(false))
+            .collect();
+        files.sort();
+
+        Ok(files)
+    }
+
+    fn cached_checksum(&self, path: &Path) -> Option<String> {
+        let metadata = fs::metadata(path).ok()?;
+        let cache = self.checksums.lock().unwrap();
+        let cached = cache.get(path)?;
+
+        if cached.size == metadata.len() && cached.modified == metadata.modified().ok() {
+            Some(cached.sha1.clone())
+        } else {
+            None
+        }
+    }
+
+    // SHA-1 of the file, computed once per size/mtime
+    fn checksum(&self, path: &Path) -> Result<String, String> {
+        if let Some(sha1) = self.cached_checksum(path) {
+            return Ok(sha1);
+        }
+
+        let metadata = fs::metadata(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let file = File::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+        println!("Computing checksum for {}", path.display());
+
+        let mut reader = BufReader::new(file);
+        let mut hasher = Sha1::new();
+        let mut buffer = vec![0u8; 1 << 20];
+        loop {
+            let read = reader.read(&mut buffer)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        let sha1: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+
+        self.checksums.lock().unwrap().insert(path.to_path_buf(), CachedChecksum {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            sha1: sha1.clone(),
+        });
+
+        Ok(sha1)
+    }
+}
+
+// Read and validate the ggml header of a model file
+pub fn read_header(path: &Path) -> Result<ModelHeader, String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    // Magic followed by 11 little-endian i32 hyperparameters
+    let mut bytes = [0u8; 48];
+    file.read_exact(&mut bytes)
+        .map_err(|_| format!("{} is too short to be a ggml model", path.display()))?;
+
+    let field = |i: usize| i32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]]);
+
+    let magic = field(0) as u32;
+    if magic != GGML_MAGIC {
+        return Err(format!("{} is not a ggml model (bad magic 0x{:08x})", path.display(), magic));
+    }
+
+    let header = ModelHeader {
+        n_vocab: field(1),
+        n_audio_ctx: field(2),
+        n_audio_state: field(3),
+        n_audio_head: field(4),
+        n_audio_layer: field(5),
+        n_text_ctx: field(6),
+        n_text_state: field(7),
+        n_text_head: field(8),
+        n_text_layer: field(9),
+        n_mels: field(10),
+        ftype: field(11),
+    };
+
+    // Catch files with a valid magic but a garbage header
+    if header.n_vocab <= 0 || header.n_audio_layer <= 0 || header.n_text_layer <= 0 || header.n_mels <= 0 {
+        return Err(format!("{} has an invalid ggml header", path.display()));
+    }
+
+    Ok(header)
+}
+
+// Compare a checksum with the published or sidecar (`<file>.sha1`) reference
+fn compare_checksum(path: &Path, sha1: &str) -> Integrity {
+    match expected_checksum(path) {
+        Some(expected) if expected.eq_ignore_ascii_case(sha1) => Integrity::Verified,
+        Some(_) => Integrity::Mismatch,
+        None => Integrity::Unknown,
+    }
+}
+
+fn expected_checksum(path: &Path) -> Option<String> {
+    // A sidecar file next to the model takes precedence over the built-in table
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha1");
+    if let Ok(contents) = fs::read_to_string(PathBuf::from(sidecar)) {
+        if let Some(checksum) = contents.split_whitespace().next() {
+            return Some(checksum.to_string());
+        }
+    }
+
+    let file_name = path.file_name()?.to_str()?;
+    KNOWN_CHECKSUMS
+        .iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, checksum)| checksum.to_string())
+}
+
+fn model_info(path: &Path, header: &ModelHeader, integrity: Integrity, active_path: Option<&Path>) -> ModelInfo {
+    ModelInfo {
+        id: path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        path: path.display().to_string(),
+        size_bytes: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        model_type: header.model_type().to_string(),
+        multilingual: header.is_multilingual(),
+        quantization: header.quantization().to_string(),
+        n_mels: header.n_mels,
+        integrity,
+        is_active: active_path.map(|active| active == path).unwrap_or(false),
+    }
+}
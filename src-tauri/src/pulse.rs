@@ -0,0 +1,319 @@
+// System audio on Linux, captured straight from a PulseAudio sink's monitor source.
+// Every output device has a monitor that carries whatever is playing on it, so no
+// loopback module or virtual cable has to be set up first. PipeWire desktops serve
+// the same API through pipewire-pulse.
+
+use serde::Serialize;
+
+// An output device's monitor, as listed to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct MonitorSource {
+    // PulseAudio source name, e.g. "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor"
+    pub name: String,
+    // Human readable name of the monitored output
+    pub description: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Whether this monitors the current default output
+    pub is_default: bool,
+}
+
+#[cfg(target_os = "linux")]
+mod native {
+    use super::MonitorSource;
+    use libpulse_binding as pulse;
+    use libpulse_simple_binding::Simple;
+    use pulse::callbacks::ListResult;
+    use pulse::context::{Context, FlagSet, State};
+    use pulse::def::BufferAttr;
+    use pulse::mainloop::standard::{IterateResult, Mainloop};
+    use pulse::operation::{Operation, State as OperationState};
+    use pulse::sample::{Format, Spec};
+    use pulse::stream::Direction;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const APP_NAME: &str = "Disrust Captioner";
+
+    // Audio delivered per read; short enough that stopping a capture is prompt
+    const READ_MILLIS: u32 = 20;
+
+    // Every sink monitor on the server, the default output's first
+    pub fn list_monitor_sources() -> Result<Vec<MonitorSource>, String> {
+        let mut mainloop = Mainloop::new().ok_or("Failed to create a PulseAudio main loop")?;
+        let mut context = Context::new(&mainloop, APP_NAME).ok_or("Failed to create a PulseAudio context")?;
+
+        context
+            .connect(None, FlagSet::NOAUTOSPAWN, None)
+            .map_err(|e| format!("Failed to connect to the PulseAudio server: {}", e))?;
+
+        let result = query_monitors(&mut mainloop, &context);
+        context.disconnect();
+        result
+    }
+
+    fn query_monitors(mainloop: &mut Mainloop, context: &Context) -> Result<Vec<MonitorSource>, String> {
+        loop {
+            iterate(mainloop)?;
+            match context.get_state() {
+                State::Ready => break,
+                State::Failed | State::Terminated => {
+                    return Err("Failed to connect to the PulseAudio server".to_string());
+                }
+                _ => {}
+            }
+        }
+
+        let introspect = context.introspect();
+
+        let default_sink = Rc::new(RefCell::new(None));
+        let operation = introspect.get_server_info({
+            let default_sink = default_sink.clone();
+            move |info| {
+                *default_sink.borrow_mut() = info.default_sink_name.as_ref().map(|name| name.to_string());
+            }
+        });
+        wait_for(mainloop, &operation)?;
+
+        let monitors = Rc::new(RefCell::new(Vec::new()));
+        let operation = introspect.get_source_info_list({
+            let monitors = monitors.clone();
+            let default_sink = default_sink.borrow().clone();
+            move |result| {
+                let ListResult::Item(info) = result else {
+                    return;
+                };
+                // Only monitors have an owning sink
+                let (Some(name), Some(sink)) = (&info.name, &info.monitor_of_sink_name) else {
+                    return;
+                };
+
+                monitors.borrow_mut().push(MonitorSource {
+                    name: name.to_string(),
+                    description: info
+                        .description
+                        .as_ref()
+                        .map(|description| description.to_string())
+                        .unwrap_or_else(|| name.to_string()),
+                    sample_rate: info.sample_spec.rate,
+                    channels: info.sample_spec.channels as u16,
+                    is_default: default_sink.as_deref() == Some(sink.as_ref()),
+                });
+            }
+        });
+        wait_for(mainloop, &operation)?;
+
+        let mut monitors = monitors.take();
+        monitors.sort_by_key(|monitor| !monitor.is_default);
+        Ok(monitors)
+    }
+
+    fn iterate(mainloop: &mut Mainloop) -> Result<(), String> {
+        match mainloop.iterate(true) {
+            IterateResult::Success(_) => Ok(()),
+            IterateResult::Quit(_) => Err("The PulseAudio main loop quit unexpectedly".to_string()),
+            IterateResult::Err(e) => Err(format!("PulseAudio main loop error: {}", e)),
+        }
+    }
+
+    fn wait_for<F: ?Sized>(mainloop: &mut Mainloop, operation: &Operation<F>) -> Result<(), String> {
+        while operation.get_state() == OperationState::Running {
+            iterate(mainloop)?;
+        }
+        Ok(())
+    }
+
+    // A recording stream on a monitor source, delivering interleaved f32 samples
+    pub struct MonitorCapture {
+        stream: Simple,
+        bytes: Vec<u8>,
+        samples: Vec<f32>,
+    }
+
+    impl MonitorCapture {
+        pub fn open(monitor: &MonitorSource) -> Result<Self, String> {
+            let spec = Spec {
+                format: Format::FLOAT32NE,
+                channels: monitor.channels.clamp(1, Spec::CHANNELS_MAX as u16) as u8,
+                rate: monitor.sample_rate,
+            };
+            if !spec.is_valid() {
+                return Err(format!("Unsupported monitor format: {}Hz, {} channels", monitor.sample_rate, monitor.channels));
+            }
+
+            let frame_bytes = spec.frame_size();
+            let read_bytes = (monitor.sample_rate * READ_MILLIS / 1000) as usize * frame_bytes;
+
+            // Ask the server for fragments of one read so the latency stays low
+            let attr = BufferAttr {
+                maxlength: u32::MAX,
+                tlength: u32::MAX,
+                prebuf: u32::MAX,
+                minreq: u32::MAX,
+                fragsize: read_bytes as u32,
+            };
+
+            let stream = Simple::new(
+                None,
+                APP_NAME,
+                Direction::Record,
+                Some(&monitor.name),
+                "System audio capture",
+                &spec,
+                None,
+                Some(&attr),
+            )
+            .map_err(|e| format!("Failed to open {}: {}", monitor.description, e))?;
+
+            Ok(MonitorCapture {
+                stream,
+                bytes: vec![0; read_bytes],
+                samples: Vec::with_capacity(read_bytes / 4),
+            })
+        }
+
+        // Block until the next chunk of audio has been recorded
+        pub fn read(&mut self) -> Result<&[f32], String> {
+            self.stream
+                .read(&mut self.bytes)
+                .map_err(|e| format!("Error reading from the monitor source: {}", e))?;
+
+            self.samples.clear();
+            self.samples.extend(
+                self.bytes
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            );
+            Ok(&self.samples)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use native::{list_monitor_sources, MonitorCapture};
+
+#[cfg(not(target_os = "linux"))]
+pub fn list_monitor_sources() -> Result<Vec<MonitorSource>, String> {
+    Err("Monitor sources are only available on Linux".to_string())
+}
+
+// The monitor with the given source name, or the default output's monitor if None
+#[cfg(target_os = "linux")]
+pub fn find_monitor_source(name: Option<&str>) -> Result<MonitorSource, String> {
+    let monitors = list_monitor_sources()?;
+
+    let monitor = match name {
+        Some(name) => monitors.into_iter().find(|monitor| monitor.name == name),
+        None => monitors.into_iter().next(),
+    };
+    monitor.ok_or_else(|| match name {
+        Some(name) => format!("Output monitor '{}' not found", name),
+        None => "No output monitor found".to_string(),
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use libpulse_binding::sample::{Format, Spec};
+    use libpulse_binding::stream::Direction;
+    use libpulse_simple_binding::Simple;
+    use std::f32::consts::PI;
+    use std::process::Command;
+    use std::thread;
+
+    const SINK: &str = "disrust_captioner_test";
+    const SAMPLE_RATE: u32 = 48_000;
+    const FREQUENCY: f32 = 1_000.0;
+    const AMPLITUDE: f32 = 0.5;
+
+    // A null sink that exists for the length of a test
+    struct NullSink {
+        module: String,
+    }
+
+    impl NullSink {
+        fn load() -> Self {
+            let output = Command::new("pactl")
+                .args([
+                    "load-module",
+                    "module-null-sink",
+                    &format!("sink_name={}", SINK),
+                    &format!("rate={}", SAMPLE_RATE),
+                    "channels=1",
+                ])
+                .output()
+                .expect("pactl is needed to load the null sink");
+            assert!(
+                output.status.success(),
+                "Failed to load module-null-sink: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            NullSink {
+                module: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for NullSink {
+        fn drop(&mut self) {
+            let _ = Command::new("pactl").args(["unload-module", &self.module]).status();
+        }
+    }
+
+    // Play one second of the test tone into the null sink
+    fn play_tone() {
+        let spec = Spec {
+            format: Format::FLOAT32NE,
+            channels: 1,
+            rate: SAMPLE_RATE,
+        };
+        let playback = Simple::new(None, "Disrust Captioner test", Direction::Playback, Some(SINK), "Test tone", &spec, None, None)
+            .expect("Failed to open the null sink for playback");
+
+        let bytes: Vec<u8> = (0..SAMPLE_RATE)
+            .flat_map(|i| (AMPLITUDE * (2.0 * PI * FREQUENCY * i as f32 / SAMPLE_RATE as f32).sin()).to_ne_bytes())
+            .collect();
+        playback.write(&bytes).expect("Failed to play the test tone");
+        playback.drain().expect("Failed to play the test tone");
+    }
+
+    // Amplitude of the test tone in the first channel of a read. A read is 20ms,
+    // which holds a whole number of the tone's periods.
+    fn tone_amplitude(samples: &[f32], monitor: &MonitorSource) -> f32 {
+        let (mut real, mut imaginary, mut count) = (0.0, 0.0, 0);
+        for (i, &sample) in samples.iter().step_by(monitor.channels as usize).enumerate() {
+            let phase = 2.0 * PI * FREQUENCY * i as f32 / monitor.sample_rate as f32;
+            real += sample * phase.cos();
+            imaginary += sample * phase.sin();
+            count += 1;
+        }
+        2.0 * (real * real + imaginary * imaginary).sqrt() / count as f32
+    }
+
+    #[test]
+    #[ignore = "needs a PulseAudio or pipewire-pulse server and pactl"]
+    fn monitor_records_null_sink() {
+        let _sink = NullSink::load();
+        let monitor = find_monitor_source(Some(&format!("{}.monitor", SINK))).unwrap();
+        let mut capture = MonitorCapture::open(&monitor).unwrap();
+
+        let player = thread::spawn(play_tone);
+
+        // Two seconds of 20ms reads covers the tone and the time it takes to start
+        let mut loudest = 0.0f32;
+        for _ in 0..100 {
+            let samples = capture.read().unwrap();
+            loudest = loudest.max(tone_amplitude(samples, &monitor));
+        }
+        player.join().unwrap();
+
+        assert!(
+            (AMPLITUDE * 0.8..AMPLITUDE * 1.25).contains(&loudest),
+            "The monitor recorded the tone at amplitude {}, expected {}",
+            loudest,
+            AMPLITUDE
+        );
+    }
+}
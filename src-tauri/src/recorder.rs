@@ -0,0 +1,785 @@
+// Saving session audio for replay and re-transcription. When enabled, a session's
+// audio is exported once it stops, twice: at the device sample rate, and as the
+// 16kHz mono audio the transcriber works on. Both sit next to a manifest that
+// places each transcript segment in the recording, so any part of it can be played
+// back or transcribed again later.
+//
+// WAV and FLAC are always available. Ogg Opus needs the `opus` feature, which links libopus.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use flacenc::component::{BitRepr, Stream, StreamInfo};
+use flacenc::error::Verify;
+use flacenc::source::{Fill, FrameBuf};
+use tauri::{AppHandle, Emitter};
+use crate::audio::{CaptureSession, PausedPeriod, SessionId};
+use crate::resample::{ResampleQuality, Resampler};
+use crate::spool;
+use crate::transcript::{Segment, SourceTag};
+
+// Rate of the processed copy, the rate Whisper expects
+const PROCESSED_RATE: u32 = 16000;
+
+// Audio read from the spool and encoded at a time while exporting
+const EXPORT_CHUNK_SECS: u32 = 10;
+
+const MANIFEST_FILE: &str = "recording.json";
+
+// Manifests are updated from the export, transcription and caption threads
+static MANIFEST_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    // 32-bit float PCM
+    #[default]
+    Wav,
+    // 16-bit lossless
+    Flac,
+    // Lossy, a fraction of the size
+    Opus,
+}
+
+impl AudioFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Opus => "opus",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingOptions {
+    // Save the audio of every session from now on
+    pub enabled: bool,
+    pub format: AudioFormat,
+}
+
+// Where a session's recording is saved. Decided when the session is opened.
+#[derive(Debug, Clone)]
+pub struct RecordingTarget {
+    id: String,
+    dir: PathBuf,
+    format: AudioFormat,
+    source: Option<SourceTag>,
+    started: u64,
+}
+
+// Ties a transcript to the part of a saved recording it was transcribed from
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingLink {
+    pub recording_id: String,
+    // Position in the recording, in seconds, that the transcript's times count from
+    pub offset: f32,
+    #[serde(skip)]
+    target: RecordingTarget,
+    // Span whose segments the transcript replaces, when it was transcribed again
+    #[serde(skip)]
+    replaces: Option<(f32, f32)>,
+}
+
+// A transcript segment placed in a saved recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedSegment {
+    // Seconds into the recording
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+    pub speaker_id: Option<String>,
+}
+
+// The manifest of a saved recording
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedRecording {
+    pub id: String,
+    // Milliseconds since the Unix epoch
+    pub started: u64,
+    pub source: Option<SourceTag>,
+    pub format: AudioFormat,
+    // Rate of the original audio
+    pub sample_rate: u32,
+    pub duration_secs: f64,
+    // File names inside the recording's directory; empty until the audio is written
+    pub original: String,
+    pub processed: String,
+    // Where the session was paused. The audio has no gaps; these place them.
+    pub pauses: Vec<PausedPeriod>,
+    pub segments: Vec<RecordedSegment>,
+    // Set once both audio files are complete
+    pub complete: bool,
+}
+
+// Payload for the `recording-failed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingFailedEvent {
+    pub recording_id: String,
+    pub error: String,
+}
+
+// Formats this build can write
+pub fn supported_formats() -> Vec<AudioFormat> {
+    let mut formats = vec![AudioFormat::Wav, AudioFormat::Flac];
+    if cfg!(feature = "opus") {
+        formats.push(AudioFormat::Opus);
+    }
+    formats
+}
+
+pub fn validate(options: &RecordingOptions) -> Result<(), String> {
+    if !supported_formats().contains(&options.format) {
+        return Err("Ogg Opus recordings need a build with the `opus` feature".to_string());
+    }
+    Ok(())
+}
+
+// The target for a new session's recording, or None if recordings are off
+pub fn target(
+    dir: &Path,
+    options: &RecordingOptions,
+    session_id: SessionId,
+    source: Option<SourceTag>,
+) -> Option<RecordingTarget> {
+    if !options.enabled {
+        return None;
+    }
+
+    let id = spool::file_stem(session_id, source);
+    Some(RecordingTarget {
+        dir: dir.join(&id),
+        id,
+        format: options.format,
+        source,
+        started: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0),
+    })
+}
+
+impl RecordingTarget {
+    // Link a transcript whose times count from `offset` seconds into the recording
+    pub fn link(&self, offset: f32) -> RecordingLink {
+        RecordingLink {
+            recording_id: self.id.clone(),
+            offset,
+            target: self.clone(),
+            replaces: None,
+        }
+    }
+}
+
+impl RecordingLink {
+    // Which side of a call the recording is, when both were captured
+    pub fn source(&self) -> Option<SourceTag> {
+        self.target.source
+    }
+}
+
+// Export a stopped session's audio on a background thread. Emits `recording-saved`
+// with the manifest when done, or `recording-failed`.
+pub fn save(app: AppHandle, session: Arc<CaptureSession>) {
+    let Some(target) = session.recording().cloned() else {
+        return;
+    };
+    // Nothing was captured if the session never started
+    let Some(format) = session.info().format else {
+        return;
+    };
+
+    thread::spawn(move || match export(&session, &target, format.sample_rate) {
+        Ok(recording) => {
+            println!("Saved recording {} ({:.1}s)", recording.id, recording.duration_secs);
+            emit(&app, "recording-saved", recording);
+        }
+        Err(error) => {
+            eprintln!("Failed to save recording {}: {}", target.id, error);
+            emit(&app, "recording-failed", RecordingFailedEvent { recording_id: target.id.clone(), error });
+        }
+    });
+}
+
+fn export(session: &CaptureSession, target: &RecordingTarget, sample_rate: u32) -> Result<SavedRecording, String> {
+    fs::create_dir_all(&target.dir).map_err(|e| format!("Failed to create {}: {}", target.dir.display(), e))?;
+
+    let original_name = format!("original.{}", target.format.extension());
+    let processed_name = format!("processed-16k.{}", target.format.extension());
+    let mut original = create_writer(target.format, &target.dir.join(&original_name), sample_rate)?;
+    let mut processed = create_writer(target.format, &target.dir.join(&processed_name), PROCESSED_RATE)?;
+    let mut resampler = Resampler::new(sample_rate, PROCESSED_RATE, ResampleQuality::Balanced);
+
+    // Read back through the spool a chunk at a time, so memory use doesn't grow with the session
+    let total = session.captured_len();
+    let chunk = (sample_rate * EXPORT_CHUNK_SECS) as usize;
+    let mut position = 0;
+    while position < total {
+        let (samples, _) = session.samples_since(position, Some((position + chunk).min(total)));
+        if samples.is_empty() {
+            return Err("The session's audio could not be read back from the spool".to_string());
+        }
+
+        original.write(&samples)?;
+        processed.write(&resampler.process(&samples))?;
+        position += samples.len();
+    }
+    processed.write(&resampler.flush())?;
+    original.finish()?;
+    processed.finish()?;
+
+    update_manifest(target, |recording| {
+        recording.sample_rate = sample_rate;
+        recording.duration_secs = position as f64 / sample_rate.max(1) as f64;
+        recording.original = original_name;
+        recording.processed = processed_name;
+        recording.pauses = session.pauses();
+        recording.complete = true;
+    })
+}
+
+// Place a transcript's segments in its recording
+pub fn add_segments(link: &RecordingLink, segments: &[Segment]) {
+    let result = update_manifest(&link.target, |recording| {
+        if let Some((start, end)) = link.replaces {
+            recording.segments.retain(|segment| segment.end <= start || segment.start >= end);
+        }
+
+        recording.segments.extend(segments.iter().map(|segment| RecordedSegment {
+            start: link.offset + segment.start,
+            end: link.offset + segment.end,
+            text: segment.text.trim().to_string(),
+            speaker_id: segment.speaker_id.clone(),
+        }));
+        recording.segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    });
+
+    if let Err(e) = result {
+        eprintln!("Failed to link transcript to recording {}: {}", link.recording_id, e);
+    }
+}
+
+// Load, change and save a recording's manifest, creating it if this is the first change
+fn update_manifest(target: &RecordingTarget, change: impl FnOnce(&mut SavedRecording)) -> Result<SavedRecording, String> {
+    let _lock = MANIFEST_LOCK.lock().unwrap();
+
+    let path = target.dir.join(MANIFEST_FILE);
+    let mut recording = read_manifest(&path).unwrap_or_else(|_| SavedRecording {
+        id: target.id.clone(),
+        started: target.started,
+        source: target.source,
+        format: target.format,
+        ..SavedRecording::default()
+    });
+    change(&mut recording);
+
+    fs::create_dir_all(&target.dir).map_err(|e| format!("Failed to create {}: {}", target.dir.display(), e))?;
+    let json = serde_json::to_string_pretty(&recording)
+        .map_err(|e| format!("Failed to serialize recording: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+
+    Ok(recording)
+}
+
+fn read_manifest(path: &Path) -> Result<SavedRecording, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+// Saved recordings, newest first
+pub fn list(dir: &Path) -> Vec<SavedRecording> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut recordings: Vec<SavedRecording> = entries
+        .flatten()
+        .filter_map(|entry| read_manifest(&entry.path().join(MANIFEST_FILE)).ok())
+        .collect();
+
+    recordings.sort_by_key(|recording| std::cmp::Reverse(recording.started));
+    recordings
+}
+
+pub fn delete(dir: &Path, id: &str) -> Result<(), String> {
+    let path = recording_dir(dir, id)?;
+    fs::remove_dir_all(&path).map_err(|e| format!("Failed to delete {}: {}", path.display(), e))
+}
+
+fn recording_dir(dir: &Path, id: &str) -> Result<PathBuf, String> {
+    // Ids come from the frontend; don't let them point outside the recordings directory
+    if id.is_empty() || id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid recording id: {}", id));
+    }
+
+    let path = dir.join(id);
+    if !path.join(MANIFEST_FILE).exists() {
+        return Err(format!("No saved recording with id {}", id));
+    }
+    Ok(path)
+}
+
+// Seconds `start..end` of a saved recording's 16kHz audio
+pub fn read_processed(dir: &Path, id: &str, start: f32, end: f32) -> Result<Vec<f32>, String> {
+    let path = recording_dir(dir, id)?;
+    let recording = read_manifest(&path.join(MANIFEST_FILE))?;
+    if !recording.complete {
+        return Err("The recording is still being saved".to_string());
+    }
+
+    let from = (start.max(0.0) * PROCESSED_RATE as f32) as usize;
+    let to = (end.max(0.0) * PROCESSED_RATE as f32) as usize;
+    if from >= to {
+        return Err("The requested span is empty".to_string());
+    }
+
+    let path = path.join(&recording.processed);
+    match recording.format {
+        AudioFormat::Wav => read_wav(&path, from, to),
+        AudioFormat::Flac => read_flac(&path, from, to),
+        #[cfg(feature = "opus")]
+        AudioFormat::Opus => ogg_opus::read(&path, from, to),
+        #[cfg(not(feature = "opus"))]
+        AudioFormat::Opus => Err("Ogg Opus recordings need a build with the `opus` feature".to_string()),
+    }
+}
+
+// A span of a saved recording as a 16-bit WAV file, for playback in the frontend
+pub fn clip(dir: &Path, id: &str, start: f32, end: f32) -> Result<Vec<u8>, String> {
+    // A WAV file can't hold more than 4 GiB; check before reading that much audio
+    let requested = ((end - start).max(0.0) as f64 * PROCESSED_RATE as f64) as u64;
+    if clip_riff_len(requested).is_none() {
+        return Err("The clip is too long to play as a WAV file".to_string());
+    }
+
+    let samples = read_processed(dir, id, start, end)?;
+    let riff_len = clip_riff_len(samples.len() as u64)
+        .ok_or_else(|| "The clip is too long to play as a WAV file".to_string())?;
+    let data_bytes = riff_len - 36;
+
+    let mut wav = Vec::with_capacity(44 + data_bytes as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&riff_len.to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&PROCESSED_RATE.to_le_bytes());
+    wav.extend_from_slice(&(PROCESSED_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_bytes.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&to_i16(sample).to_le_bytes());
+    }
+    Ok(wav)
+}
+
+// The RIFF chunk size of a 16-bit mono clip of `samples` samples, if it fits in a WAV header
+fn clip_riff_len(samples: u64) -> Option<u32> {
+    u32::try_from(samples.checked_mul(2)?.checked_add(36)?).ok()
+}
+
+// Link for transcribing a span of a saved recording again; its segments replace the span's old ones
+pub fn relink(dir: &Path, id: &str, start: f32, end: f32) -> Result<RecordingLink, String> {
+    let path = recording_dir(dir, id)?;
+    let recording = read_manifest(&path.join(MANIFEST_FILE))?;
+
+    let target = RecordingTarget {
+        id: recording.id,
+        dir: path,
+        format: recording.format,
+        source: recording.source,
+        started: recording.started,
+    };
+    let mut link = target.link(start);
+    link.replaces = Some((start, end));
+    Ok(link)
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+fn emit<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    if let Err(e) = app.emit(event, payload) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
+
+// Encodes mono audio to a file as it is exported
+trait AudioWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String>;
+
+    // Write out anything buffered and complete the file's header
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+fn create_writer(format: AudioFormat, path: &Path, sample_rate: u32) -> Result<Box<dyn AudioWriter>, String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let writer = BufWriter::new(file);
+
+    Ok(match format {
+        AudioFormat::Wav => Box::new(WavWriter::new(writer, path, sample_rate)?),
+        AudioFormat::Flac => Box::new(FlacWriter::new(writer, path, sample_rate)?),
+        #[cfg(feature = "opus")]
+        AudioFormat::Opus => Box::new(ogg_opus::OpusWriter::new(writer, path, sample_rate)?),
+        #[cfg(not(feature = "opus"))]
+        AudioFormat::Opus => return Err("Ogg Opus recordings need a build with the `opus` feature".to_string()),
+    })
+}
+
+// Float WAV in the spool's layout
+struct WavWriter {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    sample_rate: u32,
+    samples: u64,
+}
+
+impl WavWriter {
+    fn new(mut writer: BufWriter<File>, path: &Path, sample_rate: u32) -> Result<Self, String> {
+        spool::write_header(&mut writer, sample_rate, 1, 0)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        Ok(WavWriter {
+            writer,
+            path: path.to_path_buf(),
+            sample_rate,
+            samples: 0,
+        })
+    }
+}
+
+impl AudioWriter for WavWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        samples
+            .iter()
+            .try_for_each(|sample| self.writer.write_all(&sample.to_le_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+        self.samples += samples.len() as u64;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        let data_bytes = self.samples * 4;
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_mut().seek(SeekFrom::Start(0)))
+            .and_then(|_| spool::write_header(self.writer.get_mut(), self.sample_rate, 1, data_bytes))
+            .map_err(|e| format!("Failed to finish {}: {}", self.path.display(), e))
+    }
+}
+
+// 16-bit FLAC, encoded a block at a time. The STREAMINFO header is written first
+// as a placeholder and rewritten with the final length once all blocks are out.
+struct FlacWriter {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    config: flacenc::error::Verified<flacenc::config::Encoder>,
+    stream_info: StreamInfo,
+    framebuf: FrameBuf,
+    block: Vec<i32>,
+    frames: usize,
+    samples: usize,
+}
+
+impl FlacWriter {
+    fn new(writer: BufWriter<File>, path: &Path, sample_rate: u32) -> Result<Self, String> {
+        let config = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, e)| format!("Invalid FLAC encoder settings: {}", e))?;
+        let stream_info = StreamInfo::new(sample_rate as usize, 1, 16)
+            .map_err(|e| format!("Unsupported FLAC stream: {}", e))?;
+        let framebuf = FrameBuf::with_size(1, config.block_size)
+            .map_err(|e| format!("Unsupported FLAC block size: {}", e))?;
+
+        let mut flac = FlacWriter {
+            writer,
+            path: path.to_path_buf(),
+            block: Vec::with_capacity(config.block_size),
+            config,
+            stream_info,
+            framebuf,
+            frames: 0,
+            samples: 0,
+        };
+        flac.write_header()?;
+        Ok(flac)
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        let mut sink = flacenc::bitsink::ByteSink::new();
+        Stream::with_stream_info(self.stream_info.clone())
+            .write(&mut sink)
+            .map_err(|e| format!("Failed to encode FLAC header: {}", e))?;
+        self.writer
+            .write_all(sink.as_slice())
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    // Encode the current block, padded with silence if it is the short last one
+    fn encode_block(&mut self) -> Result<(), String> {
+        self.block.resize(self.config.block_size, 0);
+        self.framebuf
+            .fill_interleaved(&self.block)
+            .map_err(|e| format!("Failed to encode FLAC frame: {}", e))?;
+        self.block.clear();
+
+        let frame = flacenc::encode_fixed_size_frame(&self.config, &self.framebuf, self.frames, &self.stream_info)
+            .map_err(|e| format!("Failed to encode FLAC frame: {:?}", e))?;
+        self.stream_info.update_frame_info(&frame);
+        self.frames += 1;
+
+        let mut sink = flacenc::bitsink::ByteSink::new();
+        frame.write(&mut sink).map_err(|e| format!("Failed to encode FLAC frame: {}", e))?;
+        self.writer
+            .write_all(sink.as_slice())
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+impl AudioWriter for FlacWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        for &sample in samples {
+            self.block.push(to_i16(sample) as i32);
+            if self.block.len() == self.config.block_size {
+                self.encode_block()?;
+            }
+        }
+        self.samples += samples.len();
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        if !self.block.is_empty() {
+            self.encode_block()?;
+        }
+        // The padding of the last block isn't part of the stream
+        self.stream_info.set_total_samples(self.samples);
+
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.seek(SeekFrom::Start(0)).map(|_| ()))
+            .map_err(|e| format!("Failed to finish {}: {}", self.path.display(), e))?;
+        self.write_header()?;
+        self.writer.flush().map_err(|e| format!("Failed to finish {}: {}", self.path.display(), e))
+    }
+}
+
+fn read_wav(path: &Path, from: usize, to: usize) -> Result<Vec<f32>, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    spool::read_format(&mut file).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let data_bytes = file.metadata().map(|metadata| metadata.len()).unwrap_or(0).saturating_sub(spool::WAV_HEADER_LEN);
+    let available = (data_bytes / 4) as usize;
+    let to = to.min(available);
+    if from >= to {
+        return Ok(Vec::new());
+    }
+
+    file.seek(SeekFrom::Start(spool::WAV_HEADER_LEN + from as u64 * 4))
+        .and_then(|_| spool::read_samples(&mut file, (to - from) as u64 * 4))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn read_flac(path: &Path, from: usize, to: usize) -> Result<Vec<f32>, String> {
+    let mut reader = claxon::FlacReader::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    // Decoding continues into the padding of the last block, so stop at the stream's length
+    let total = reader.streaminfo().samples.map_or(usize::MAX, |samples| samples as usize);
+
+    reader
+        .samples()
+        .take(to.min(total))
+        .skip(from)
+        .map(|sample| {
+            sample
+                .map(|sample| sample as f32 / 32768.0)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+#[cfg(feature = "opus")]
+mod ogg_opus {
+    use super::AudioWriter;
+    use crate::resample::{ResampleQuality, Resampler};
+    use ogg::writing::PacketWriteEndInfo;
+    use ogg::{PacketReader, PacketWriter};
+    use opus::{Application, Channels, Decoder, Encoder};
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter};
+    use std::path::{Path, PathBuf};
+
+    // Opus only runs at these rates; anything else is resampled to 48kHz
+    const OPUS_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+
+    // Granule positions always count 48kHz samples
+    const GRANULE_RATE: u64 = 48000;
+
+    // 20ms packets
+    const FRAMES_PER_SECOND: u32 = 50;
+
+    // Largest packet libopus produces
+    const MAX_PACKET: usize = 4000;
+
+    const STREAM_SERIAL: u32 = 1;
+
+    // Mono Ogg Opus (RFC 7845)
+    pub struct OpusWriter {
+        packets: PacketWriter<'static, BufWriter<File>>,
+        path: PathBuf,
+        encoder: Encoder,
+        encoder_rate: u32,
+        // Set when the input rate isn't one Opus supports
+        resampler: Option<Resampler>,
+        frame_len: usize,
+        pending: Vec<f32>,
+        // 48kHz samples of input encoded so far, and the encoder delay in the same unit
+        granule: u64,
+        pre_skip: u64,
+        // Packets are written one behind so the last one can end the stream
+        last_packet: Option<Vec<u8>>,
+    }
+
+    impl OpusWriter {
+        pub fn new(writer: BufWriter<File>, path: &Path, sample_rate: u32) -> Result<Self, String> {
+            let (encoder_rate, resampler) = if OPUS_RATES.contains(&sample_rate) {
+                (sample_rate, None)
+            } else {
+                (48000, Some(Resampler::new(sample_rate, 48000, ResampleQuality::Balanced)))
+            };
+
+            let mut encoder = Encoder::new(encoder_rate, Channels::Mono, Application::Audio)
+                .map_err(|e| format!("Failed to create Opus encoder: {}", e))?;
+            let lookahead = encoder.get_lookahead().map_err(|e| format!("Failed to create Opus encoder: {}", e))?;
+            let pre_skip = lookahead as u64 * GRANULE_RATE / encoder_rate as u64;
+
+            let mut opus = OpusWriter {
+                packets: PacketWriter::new(writer),
+                path: path.to_path_buf(),
+                encoder,
+                encoder_rate,
+                resampler,
+                frame_len: (encoder_rate / FRAMES_PER_SECOND) as usize,
+                pending: Vec::new(),
+                granule: 0,
+                pre_skip,
+                last_packet: None,
+            };
+            opus.write_headers(sample_rate)?;
+            Ok(opus)
+        }
+
+        fn write_headers(&mut self, input_rate: u32) -> Result<(), String> {
+            let mut head = Vec::with_capacity(19);
+            head.extend_from_slice(b"OpusHead");
+            head.push(1);
+            head.push(1);
+            head.extend_from_slice(&(self.pre_skip as u16).to_le_bytes());
+            head.extend_from_slice(&input_rate.to_le_bytes());
+            head.extend_from_slice(&0i16.to_le_bytes());
+            head.push(0);
+
+            let vendor = b"Disrust Captioner";
+            let mut tags = Vec::new();
+            tags.extend_from_slice(b"OpusTags");
+            tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+            tags.extend_from_slice(vendor);
+            tags.extend_from_slice(&0u32.to_le_bytes());
+
+            self.write_packet(head, PacketWriteEndInfo::EndPage, 0)?;
+            self.write_packet(tags, PacketWriteEndInfo::EndPage, 0)
+        }
+
+        fn write_packet(&mut self, packet: Vec<u8>, end: PacketWriteEndInfo, granule: u64) -> Result<(), String> {
+            self.packets
+                .write_packet(packet, STREAM_SERIAL, end, granule)
+                .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+        }
+
+        fn encode_frame(&mut self) -> Result<(), String> {
+            let frame: Vec<f32> = self.pending.drain(..self.frame_len).collect();
+            let packet = self
+                .encoder
+                .encode_vec_float(&frame, MAX_PACKET)
+                .map_err(|e| format!("Failed to encode Opus packet: {}", e))?;
+
+            if let Some(previous) = self.last_packet.replace(packet) {
+                let granule = self.pre_skip + self.granule;
+                self.write_packet(previous, PacketWriteEndInfo::NormalPacket, granule)?;
+            }
+            self.granule += GRANULE_RATE / FRAMES_PER_SECOND as u64;
+            Ok(())
+        }
+    }
+
+    impl AudioWriter for OpusWriter {
+        fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+            match self.resampler.as_mut() {
+                Some(resampler) => self.pending.extend(resampler.process(samples)),
+                None => self.pending.extend_from_slice(samples),
+            }
+            while self.pending.len() >= self.frame_len {
+                self.encode_frame()?;
+            }
+            Ok(())
+        }
+
+        fn finish(mut self: Box<Self>) -> Result<(), String> {
+            if let Some(resampler) = self.resampler.as_mut() {
+                let remaining = resampler.flush();
+                self.pending.extend(remaining);
+            }
+
+            // The last frame is padded with silence; the final granule position trims it off
+            let end = self.pre_skip + self.granule + self.pending.len() as u64 * GRANULE_RATE / self.encoder_rate as u64;
+            if !self.pending.is_empty() || self.last_packet.is_none() {
+                self.pending.resize(self.frame_len, 0.0);
+                self.encode_frame()?;
+            }
+
+            let last = self.last_packet.take().unwrap_or_default();
+            self.write_packet(last, PacketWriteEndInfo::EndStream, end)
+        }
+    }
+
+    // Decode 16kHz samples `from..to` of an Ogg Opus file
+    pub fn read(path: &Path, from: usize, to: usize) -> Result<Vec<f32>, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut packets = PacketReader::new(BufReader::new(file));
+        let mut next_packet = || {
+            packets
+                .read_packet()
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        };
+
+        let head = next_packet()?.ok_or_else(|| format!("{} is empty", path.display()))?;
+        if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+            return Err(format!("{} is not an Ogg Opus file", path.display()));
+        }
+        // Skip the encoder delay, given at 48kHz
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize / 3;
+        next_packet()?;
+
+        let mut decoder = Decoder::new(16000, Channels::Mono).map_err(|e| format!("Failed to create Opus decoder: {}", e))?;
+        let mut output = vec![0.0; 16000 * 120 / 1000];
+        let mut samples = Vec::new();
+        while samples.len() < pre_skip + to {
+            let Some(packet) = next_packet()? else { break };
+            let decoded = decoder
+                .decode_float(&packet.data, &mut output, false)
+                .map_err(|e| format!("Failed to decode {}: {}", path.display(), e))?;
+            samples.extend_from_slice(&output[..decoded]);
+        }
+
+        let end = (pre_skip + to).min(samples.len());
+        let start = (pre_skip + from).min(end);
+        Ok(samples[start..end].to_vec())
+    }
+}
@@ -0,0 +1,295 @@
+// Band-limited sample rate conversion. Each output sample is a Kaiser-windowed
+// sinc interpolation of the input, with the cutoff below the lower of the two
+// Nyquist rates so that energy above it is filtered out instead of aliasing
+// into the speech band.
+
+use std::f64::consts::PI;
+
+// Filter taps stored per input sample; in-between offsets are interpolated
+const TABLE_RESOLUTION: usize = 256;
+
+// Trade-off between CPU cost and stopband rejection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+    // Short filter for live analysis such as voice activity detection (at least -60dB)
+    Fast,
+    // Audio for transcription (at least -85dB)
+    #[default]
+    Balanced,
+}
+
+impl ResampleQuality {
+    // Sinc zero crossings on each side of the filter centre
+    fn zero_crossings(self) -> usize {
+        match self {
+            ResampleQuality::Fast => 8,
+            ResampleQuality::Balanced => 24,
+        }
+    }
+
+    // Kaiser window shape; larger values give a deeper stopband and a wider transition
+    fn kaiser_beta(self) -> f64 {
+        match self {
+            ResampleQuality::Fast => 6.0,
+            ResampleQuality::Balanced => 8.5,
+        }
+    }
+
+    // Cutoff as a fraction of the lower Nyquist rate
+    fn rolloff(self) -> f64 {
+        match self {
+            ResampleQuality::Fast => 0.8,
+            ResampleQuality::Balanced => 0.88,
+        }
+    }
+}
+
+// Streaming resampler. Input can be fed in chunks of any size; filter history is
+// carried between chunks so the output is the same as converting in one go.
+pub struct Resampler {
+    passthrough: bool,
+    // Input samples per output sample
+    step: f64,
+    // Filter half-length in input samples, and the same rounded up to whole samples
+    half_width: f64,
+    history: usize,
+    // Filter response at 1/TABLE_RESOLUTION input sample steps from the centre
+    table: Vec<f32>,
+    // Input not yet fully consumed, preceded by the history the filter still needs
+    buffer: Vec<f32>,
+    // Stream position of the first sample in `buffer`, counting the leading silence
+    buffer_start: u64,
+    input_count: u64,
+    output_count: u64,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Self {
+        let step = from_rate as f64 / to_rate as f64;
+
+        // Cutoff in cycles per input sample, relative to the input Nyquist rate
+        let scale = (to_rate as f64 / from_rate as f64).min(1.0) * quality.rolloff();
+        let half_width = quality.zero_crossings() as f64 / scale;
+        let beta = quality.kaiser_beta();
+
+        let table_len = (half_width * TABLE_RESOLUTION as f64).ceil() as usize + 2;
+        let table = (0..table_len)
+            .map(|i| {
+                let distance = i as f64 / TABLE_RESOLUTION as f64;
+                if distance > half_width {
+                    return 0.0;
+                }
+                let window = bessel_i0(beta * (1.0 - (distance / half_width).powi(2)).sqrt()) / bessel_i0(beta);
+                (scale * sinc(scale * distance) * window) as f32
+            })
+            .collect();
+
+        // Start with silence in the history so the first output lines up with the first input
+        let history = half_width.ceil() as usize;
+
+        Resampler {
+            passthrough: from_rate == to_rate,
+            step,
+            half_width,
+            history,
+            table,
+            buffer: vec![0.0; history],
+            buffer_start: 0,
+            input_count: 0,
+            output_count: 0,
+        }
+    }
+
+    // Resample the next chunk of input, returning whatever output is complete
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.passthrough {
+            return input.to_vec();
+        }
+
+        self.input_count += input.len() as u64;
+        self.buffer.extend_from_slice(input);
+
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        let last_index = (self.buffer_start + self.buffer.len() as u64) as f64 - 1.0;
+
+        loop {
+            let position = self.position(self.output_count);
+            if position + self.half_width > last_index {
+                break;
+            }
+            output.push(self.interpolate(position));
+            self.output_count += 1;
+        }
+
+        // Drop input that is behind the filter's reach
+        let reach = (self.position(self.output_count) - self.half_width).floor().max(0.0) as u64;
+        let consumed = (reach.saturating_sub(self.buffer_start) as usize).min(self.buffer.len());
+        self.buffer.drain(..consumed);
+        self.buffer_start += consumed as u64;
+
+        output
+    }
+
+    // Finish the stream, returning the output still held back for lookahead
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.passthrough {
+            return Vec::new();
+        }
+
+        let expected = (self.input_count as f64 / self.step).ceil() as u64;
+        let remaining = expected.saturating_sub(self.output_count) as usize;
+
+        // Pad with silence so the filter can reach past the end of the input
+        let padding = vec![0.0; self.history + 1];
+        let mut output = self.process(&padding);
+        output.truncate(remaining);
+
+        // Ready for a new stream
+        self.reset();
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer = vec![0.0; self.history];
+        self.buffer_start = 0;
+        self.input_count = 0;
+        self.output_count = 0;
+    }
+
+    // Stream position of output sample `index`. Worked out from the start of the stream
+    // rather than accumulated, so it comes out the same however the input is chunked.
+    fn position(&self, index: u64) -> f64 {
+        self.history as f64 + index as f64 * self.step
+    }
+
+    fn interpolate(&self, position: f64) -> f32 {
+        let first = (position - self.half_width).ceil().max(0.0) as u64;
+        let last = ((position + self.half_width).floor() as u64).min(self.buffer_start + self.buffer.len() as u64 - 1);
+
+        let mut sum = 0.0f32;
+        for index in first..=last {
+            let sample = self.buffer[(index - self.buffer_start) as usize];
+            sum += sample * self.kernel((position - index as f64).abs());
+        }
+        sum
+    }
+
+    // Filter response at `distance` input samples from the centre
+    fn kernel(&self, distance: f64) -> f32 {
+        let scaled = distance * TABLE_RESOLUTION as f64;
+        let index = scaled as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let t = (scaled - index as f64) as f32;
+        self.table[index] * (1.0 - t) + self.table[index + 1] * t
+    }
+}
+
+// Resample a complete signal
+pub fn resample(input: &[f32], from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Vec<f32> {
+    let mut resampler = Resampler::new(from_rate, to_rate, quality);
+    let mut output = resampler.process(input);
+    output.extend(resampler.flush());
+    output
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Zeroth-order modified Bessel function of the first kind, for the Kaiser window
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= (half / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A quarter second of a full-scale sine at 48kHz
+    fn tone(frequency: f64) -> Vec<f32> {
+        (0..12_000)
+            .map(|i| (2.0 * PI * frequency * i as f64 / 48_000.0).sin() as f32)
+            .collect()
+    }
+
+    // Level of a resampled tone relative to full scale, leaving out the filter's run-in at both ends
+    fn level_db(output: &[f32]) -> f64 {
+        let steady = &output[200..output.len() - 200];
+        let power = steady.iter().map(|&sample| (sample as f64).powi(2)).sum::<f64>() / steady.len() as f64;
+        10.0 * (2.0 * power).log10()
+    }
+
+    fn assert_stopband(quality: ResampleQuality, attenuation_db: f64) {
+        // Tones that would alias into the 0-8kHz band at 16kHz
+        for frequency in (8_050..24_000).step_by(100) {
+            let level = level_db(&resample(&tone(frequency as f64), 48_000, 16_000, quality));
+            assert!(
+                level <= -attenuation_db,
+                "{:?}: {}Hz came through at {:.1}dB",
+                quality,
+                frequency,
+                level
+            );
+        }
+
+        // Speech band tones pass unchanged
+        let level = level_db(&resample(&tone(1_000.0), 48_000, 16_000, quality));
+        assert!(level.abs() < 0.1, "{:?}: 1kHz came through at {:.2}dB", quality, level);
+    }
+
+    #[test]
+    fn fast_stopband() {
+        assert_stopband(ResampleQuality::Fast, 60.0);
+    }
+
+    #[test]
+    fn balanced_stopband() {
+        assert_stopband(ResampleQuality::Balanced, 85.0);
+    }
+
+    #[test]
+    fn chunked_matches_one_shot() {
+        // Deterministic noise, so every filter tap matters
+        let input: Vec<f32> = (0..20_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 8) as f32 / (1 << 24) as f32 - 0.5)
+            .collect();
+        let chunk_sizes = [1, 7, 480, 333, 4_096, 2];
+
+        for (from_rate, to_rate) in [(48_000, 16_000), (44_100, 16_000), (16_000, 48_000)] {
+            for quality in [ResampleQuality::Fast, ResampleQuality::Balanced] {
+                let expected = resample(&input, from_rate, to_rate, quality);
+
+                let mut resampler = Resampler::new(from_rate, to_rate, quality);
+                let mut output = Vec::new();
+                let mut rest = &input[..];
+                for &size in chunk_sizes.iter().cycle() {
+                    if rest.is_empty() {
+                        break;
+                    }
+                    let (chunk, remaining) = rest.split_at(size.min(rest.len()));
+                    output.extend(resampler.process(chunk));
+                    rest = remaining;
+                }
+                output.extend(resampler.flush());
+
+                assert_eq!(output, expected, "{:?} {} -> {}", quality, from_rate, to_rate);
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// User settings persisted as JSON in the app data directory
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use crate::devices::DeviceLossPolicy;
+use crate::loopback::{self, DeviceRule};
+use crate::recorder::RecordingOptions;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // How virtual loopback devices are recognised for system audio, highest priority first
+    pub loopback_rules: Vec<DeviceRule>,
+    // What a capture does when its input device disappears
+    pub device_loss: DeviceLossPolicy,
+    // Whether and how session audio is saved
+    pub recording: RecordingOptions,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            loopback_rules: loopback::default_rules(),
+            device_loss: DeviceLossPolicy::default(),
+            recording: RecordingOptions::default(),
+        }
+    }
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    // Load the settings from `path`, using the defaults if the file is missing or unreadable
+    pub fn load(path: PathBuf) -> Self {
+        let settings = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                eprintln!("Ignoring unreadable settings in {}: {}", path.display(), e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        };
+
+        SettingsStore {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    // Change the settings and save them
+    pub fn update(&self, change: impl FnOnce(&mut Settings)) -> Result<Settings, String> {
+        let mut settings = self.settings.lock().unwrap();
+        change(&mut settings);
+        self.save(&settings)?;
+        Ok(settings.clone())
+    }
+
+    fn save(&self, settings: &Settings) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        let json = serde_json::to_string_pretty(settings)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+        fs::write(&self.path, json)
+            .map_err(|e| format!("Failed to save settings to {}: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,159 @@
+// Enrolled voice profiles, persisted so speakers keep their names across sessions
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use crate::diarize::VectorKind;
+
+// Prefix of enrolled speaker ids, keeping them apart from per-recording "SpeakerN" ids
+const PROFILE_ID_PREFIX: &str = "voice-";
+
+// A named voice and the speaker vector it was enrolled with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeakerProfile {
+    pub id: String,
+    pub name: String,
+    pub kind: VectorKind,
+    // Mean speaker vector of every segment enrolled for this voice
+    pub embedding: Vec<f32>,
+    // Number of segments averaged into the embedding
+    pub segments: usize,
+}
+
+// Speaker profiles stored as JSON in the app data directory
+pub struct SpeakerStore {
+    path: PathBuf,
+    profiles: Mutex<Vec<SpeakerProfile>>,
+}
+
+impl SpeakerStore {
+    // Load the profiles from `path`, starting empty if the file is missing or unreadable
+    pub fn load(path: PathBuf) -> Self {
+        let profiles = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                eprintln!("Ignoring unreadable speaker profiles in {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        SpeakerStore {
+            path,
+            profiles: Mutex::new(profiles),
+        }
+    }
+
+    pub fn list(&self) -> Vec<SpeakerProfile> {
+        self.profiles.lock().unwrap().clone()
+    }
+
+    pub fn is_profile_id(id: &str) -> bool {
+        id.starts_with(PROFILE_ID_PREFIX)
+    }
+
+    // Enroll segments of a voice. Updates the profile `id` if given, otherwise creates a new one.
+    pub fn enroll(&self, id: Option<&str>, name: &str, kind: VectorKind, vectors: &[Vec<f32>]) -> Result<SpeakerProfile, String> {
+        if vectors.is_empty() {
+            return Err("No speech to enroll for this speaker".to_string());
+        }
+
+        let mut profiles = self.profiles.lock().unwrap();
+        let sum = vectors.iter().fold(vec![0.0f32; vectors[0].len()], |mut sum, vector| {
+            for (total, value) in sum.iter_mut().zip(vector) {
+                *total += value;
+            }
+            sum
+        });
+
+        let existing = id.and_then(|id| profiles.iter().position(|profile| profile.id == id));
+
+        let profile = match existing {
+            // Fold the new segments into the running mean if the vectors are comparable
+            Some(index) => {
+                let profile = &mut profiles[index];
+                profile.name = name.to_string();
+
+                if profile.kind == kind && profile.embedding.len() == sum.len() {
+                    let total = (profile.segments + vectors.len()) as f32;
+                    for (value, added) in profile.embedding.iter_mut().zip(&sum) {
+                        *value = (*value * profile.segments as f32 + added) / total;
+                    }
+                    profile.segments += vectors.len();
+                } else {
+                    profile.kind = kind;
+                    profile.embedding = sum.iter().map(|value| value / vectors.len() as f32).collect();
+                    profile.segments = vectors.len();
+                }
+
+                profile.clone()
+            }
+            None => {
+                let profile = SpeakerProfile {
+                    id: next_profile_id(&profiles),
+                    name: name.to_string(),
+                    kind,
+                    embedding: sum.iter().map(|value| value / vectors.len() as f32).collect(),
+                    segments: vectors.len(),
+                };
+                profiles.push(profile.clone());
+                profile
+            }
+        };
+
+        self.save(&profiles)?;
+        println!("Enrolled speaker {} as {} ({} segments)", profile.id, profile.name, profile.segments);
+        Ok(profile)
+    }
+
+    pub fn rename(&self, id: &str, name: &str) -> Result<SpeakerProfile, String> {
+        let mut profiles = self.profiles.lock().unwrap();
+        let profile = profiles
+            .iter_mut()
+            .find(|profile| profile.id == id)
+            .ok_or_else(|| format!("No speaker profile with id {}", id))?;
+
+        profile.name = name.to_string();
+        let profile = profile.clone();
+
+        self.save(&profiles)?;
+        Ok(profile)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), String> {
+        let mut profiles = self.profiles.lock().unwrap();
+        let count = profiles.len();
+        profiles.retain(|profile| profile.id != id);
+
+        if profiles.len() == count {
+            return Err(format!("No speaker profile with id {}", id));
+        }
+
+        self.save(&profiles)
+    }
+
+    fn save(&self, profiles: &[SpeakerProfile]) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        let json = serde_json::to_string_pretty(profiles)
+            .map_err(|e| format!("Failed to serialize speaker profiles: {}", e))?;
+
+        fs::write(&self.path, json)
+            .map_err(|e| format!("Failed to save speaker profiles to {}: {}", self.path.display(), e))
+    }
+}
+
+// Next unused "voice-N" id
+fn next_profile_id(profiles: &[SpeakerProfile]) -> String {
+    let next = profiles
+        .iter()
+        .filter_map(|profile| profile.id.strip_prefix(PROFILE_ID_PREFIX)?.parse::<u32>().ok())
+        .max()
+        .map(|max| max + 1)
+        .unwrap_or(1);
+
+    format!("{}{}", PROFILE_ID_PREFIX, next)
+}
@@ -0,0 +1,519 @@
+// Session audio storage. Everything a session captures is appended to a WAV file
+// in the spool directory as it arrives, and only the most recent LIVE_WINDOW_SECS
+// stay in memory for live transcription; older audio is read back from disk.
+// Memory use no longer grows with the length of a meeting, and the spool of a
+// session that never stopped (the app crashed or was killed) can be recovered
+// on the next start.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::audio::SessionId;
+use crate::channels;
+use crate::transcript::SourceTag;
+
+// Audio kept in memory for the streaming transcriber and utterance segments
+const LIVE_WINDOW_SECS: u32 = 120;
+
+// How often the WAV header is brought up to date, bounding what a crash can leave unaccounted for
+const HEADER_INTERVAL_SECS: u32 = 5;
+
+// Spool files of sessions that are still capturing; renamed to plain .wav once stopped
+const PARTIAL_EXTENSION: &str = ".partial.wav";
+const COMPLETE_EXTENSION: &str = ".wav";
+
+// Marks the per-channel spool kept alongside the mono one
+const CHANNELS_SUFFIX: &str = "-channels";
+
+// 32-bit float PCM, with the canonical 44 byte header. Saved recordings use the same layout.
+pub const WAV_HEADER_LEN: u64 = 44;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+// A recording left behind by a session that never stopped
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoverableRecording {
+    pub id: String,
+    // Milliseconds since the Unix epoch
+    pub started: u64,
+    pub duration_secs: f64,
+    pub sample_rate: u32,
+    pub source: Option<SourceTag>,
+}
+
+// An append-only WAV file of interleaved f32 frames. The file is deleted when
+// the writer is dropped; only a crash leaves it behind.
+pub struct SpoolFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    sample_rate: u32,
+    channels: u16,
+    frames: u64,
+    frames_at_header: u64,
+    failed: bool,
+}
+
+impl SpoolFile {
+    fn create(path: PathBuf, sample_rate: u32, channels: u16) -> Result<Self, String> {
+        let file = File::create(&path)
+            .map_err(|e| format!("Failed to create spool file {}: {}", path.display(), e))?;
+
+        let mut writer = BufWriter::new(file);
+        write_header(&mut writer, sample_rate, channels, 0)
+            .map_err(|e| format!("Failed to write spool file {}: {}", path.display(), e))?;
+
+        Ok(SpoolFile {
+            path,
+            writer,
+            sample_rate,
+            channels: channels.max(1),
+            frames: 0,
+            frames_at_header: 0,
+            failed: false,
+        })
+    }
+
+    // Append interleaved frames. A write error is reported once; the capture carries
+    // on, but audio that has left the live window can no longer be read back.
+    pub fn append(&mut self, samples: &[f32]) {
+        if self.failed {
+            return;
+        }
+
+        let result = samples
+            .iter()
+            .try_for_each(|sample| self.writer.write_all(&sample.to_le_bytes()))
+            .and_then(|_| {
+                self.frames += (samples.len() / self.channels as usize) as u64;
+                if self.frames - self.frames_at_header >= (self.sample_rate * HEADER_INTERVAL_SECS) as u64 {
+                    self.update_header()?;
+                }
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            eprintln!("Failed to write spool file {}: {}", self.path.display(), e);
+            self.failed = true;
+        }
+    }
+
+    // Write buffered audio and the current length to disk
+    fn update_header(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let data_bytes = self.data_bytes();
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        write_header(file, self.sample_rate, self.channels, data_bytes)?;
+        file.seek(SeekFrom::End(0))?;
+        self.frames_at_header = self.frames;
+        Ok(())
+    }
+
+    fn data_bytes(&self) -> u64 {
+        self.frames * self.channels as u64 * 4
+    }
+
+    // Read interleaved frames `from..to` back from disk
+    fn read(&mut self, from: u64, to: u64) -> Result<Vec<f32>, String> {
+        match self.open_read(from, to)? {
+            Some(read) => read.read(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Get frames `from..to` ready to be read without the spool. Only the file is
+    // opened here; it stays readable if the spool is renamed or removed meanwhile.
+    fn open_read(&mut self, from: u64, to: u64) -> Result<Option<SpoolRead>, String> {
+        let to = to.min(self.frames);
+        if from >= to {
+            return Ok(None);
+        }
+
+        self.writer
+            .flush()
+            .map_err(|e| format!("Failed to write spool file {}: {}", self.path.display(), e))?;
+
+        let file = File::open(&self.path)
+            .map_err(|e| format!("Failed to open spool file {}: {}", self.path.display(), e))?;
+        Ok(Some(SpoolRead {
+            path: self.path.clone(),
+            file,
+            frame_bytes: self.channels as u64 * 4,
+            from,
+            frames: to - from,
+        }))
+    }
+
+    // The session stopped cleanly: finalize the header and stop treating the file as recoverable
+    fn finish(&mut self) {
+        if let Err(e) = self.update_header() {
+            eprintln!("Failed to finalize spool file {}: {}", self.path.display(), e);
+        }
+
+        if let Some(name) = self.path.to_str().and_then(|path| path.strip_suffix(PARTIAL_EXTENSION)) {
+            let complete = PathBuf::from(format!("{}{}", name, COMPLETE_EXTENSION));
+            match fs::rename(&self.path, &complete) {
+                Ok(()) => self.path = complete,
+                Err(e) => eprintln!("Failed to rename spool file {}: {}", self.path.display(), e),
+            }
+        }
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Frames of a spool file opened by `SpoolFile::open_read`
+struct SpoolRead {
+    path: PathBuf,
+    file: File,
+    frame_bytes: u64,
+    from: u64,
+    frames: u64,
+}
+
+impl SpoolRead {
+    fn read(mut self) -> Result<Vec<f32>, String> {
+        self.file
+            .seek(SeekFrom::Start(WAV_HEADER_LEN + self.from * self.frame_bytes))
+            .and_then(|_| read_samples(&mut self.file, self.frames * self.frame_bytes))
+            .map_err(|e| format!("Failed to read spool file {}: {}", self.path.display(), e))
+    }
+}
+
+// Session audio copied out of the live window, and whatever has to be read from disk
+// to go before it. The disk read is left to `complete`, so it doesn't hold up the
+// recording thread while the session's audio is locked.
+pub struct PendingRead {
+    spooled: Option<SpoolRead>,
+    live: Vec<f32>,
+}
+
+impl PendingRead {
+    pub fn complete(self) -> Vec<f32> {
+        let mut samples = match self.spooled.map(SpoolRead::read) {
+            Some(Ok(spooled)) => spooled,
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        samples.extend(self.live);
+        samples
+    }
+}
+
+// Mono session audio: a bounded live window in memory backed by the spool file
+pub struct SpooledAudio {
+    live: VecDeque<f32>,
+    // Session offset of the first sample in `live`
+    live_start: usize,
+    capacity: usize,
+    spool: SpoolFile,
+}
+
+impl SpooledAudio {
+    pub fn push(&mut self, samples: &[f32]) {
+        self.spool.append(samples);
+        self.live.extend(samples);
+
+        let excess = self.live.len().saturating_sub(self.capacity);
+        self.live.drain(..excess);
+        self.live_start += excess;
+    }
+
+    // Total samples captured
+    pub fn len(&self) -> usize {
+        self.live_start + self.live.len()
+    }
+
+    // Samples `from..to` of the session, from memory where possible. Those that have
+    // left the live window are only read from disk by `PendingRead::complete`.
+    pub fn read(&mut self, from: usize, to: usize) -> PendingRead {
+        let to = to.min(self.len());
+        let mut pending = PendingRead {
+            spooled: None,
+            live: Vec::new(),
+        };
+        if from >= to {
+            return pending;
+        }
+
+        if from < self.live_start {
+            match self.spool.open_read(from as u64, self.live_start.min(to) as u64) {
+                Ok(spooled) => pending.spooled = spooled,
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        let live_from = from.max(self.live_start) - self.live_start;
+        let live_to = to.saturating_sub(self.live_start);
+        if live_from < live_to {
+            pending.live.extend(self.live.range(live_from..live_to));
+        }
+        pending
+    }
+
+    pub fn finish(&mut self) {
+        self.spool.finish();
+    }
+}
+
+// Interleaved audio of every channel, kept only on disk
+pub struct SpooledChannels {
+    spool: SpoolFile,
+}
+
+impl SpooledChannels {
+    pub fn push(&mut self, data: &[f32]) {
+        self.spool.append(data);
+    }
+
+    pub fn channels(&self) -> usize {
+        self.spool.channels as usize
+    }
+
+    // Frames captured
+    pub fn len(&self) -> usize {
+        self.spool.frames as usize
+    }
+
+    // Frames `from..to` of one channel
+    pub fn read_channel(&mut self, channel: usize, from: usize, to: usize) -> Result<Vec<f32>, String> {
+        let data = self.spool.read(from as u64, to as u64)?;
+        let mut buffers = vec![Vec::new(); self.channels()];
+        channels::split_into(&data, &mut buffers);
+        Ok(buffers.swap_remove(channel))
+    }
+
+    pub fn finish(&mut self) {
+        self.spool.finish();
+    }
+}
+
+// Start spooling a session's audio into `dir`
+pub fn create(
+    dir: &Path,
+    session_id: SessionId,
+    source: Option<SourceTag>,
+    sample_rate: u32,
+    channels: Option<u16>,
+) -> Result<(SpooledAudio, Option<SpooledChannels>), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let name = file_stem(session_id, source);
+
+    let audio = SpooledAudio {
+        live: VecDeque::new(),
+        live_start: 0,
+        capacity: (sample_rate * LIVE_WINDOW_SECS) as usize,
+        spool: SpoolFile::create(dir.join(format!("{}{}", name, PARTIAL_EXTENSION)), sample_rate, 1)?,
+    };
+
+    let channels = match channels {
+        Some(channels) => {
+            let path = dir.join(format!("{}{}{}", name, CHANNELS_SUFFIX, PARTIAL_EXTENSION));
+            Some(SpooledChannels {
+                spool: SpoolFile::create(path, sample_rate, channels)?,
+            })
+        }
+        None => None,
+    };
+
+    Ok((audio, channels))
+}
+
+// Clear out what previous runs left behind: completed spools of sessions that were
+// still open when the app quit, and per-channel spools, which can't be recovered
+pub fn clean_up(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let recoverable = name.ends_with(PARTIAL_EXTENSION) && !name.contains(CHANNELS_SUFFIX);
+        if name.ends_with(COMPLETE_EXTENSION) && !recoverable {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+// "<started ms>-<session id>-<source>", naming a session's files so they sort by
+// start time and can be described without opening them
+pub fn file_stem(session_id: SessionId, source: Option<SourceTag>) -> String {
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0);
+    let source = match source {
+        Some(SourceTag::Me) => "me",
+        Some(SourceTag::Remote) => "remote",
+        None => "any",
+    };
+    format!("{}-{}-{}", started, session_id, source)
+}
+
+// Recordings of sessions that never stopped, newest first
+pub fn list_recoverable(dir: &Path) -> Vec<RecoverableRecording> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut recordings: Vec<RecoverableRecording> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.strip_suffix(PARTIAL_EXTENSION)?.to_string();
+            if name.contains(CHANNELS_SUFFIX) {
+                return None;
+            }
+            describe(&entry.path(), &name)
+        })
+        .collect();
+
+    recordings.sort_by_key(|recording| std::cmp::Reverse(recording.started));
+    recordings
+}
+
+fn describe(path: &Path, id: &str) -> Option<RecoverableRecording> {
+    let mut parts = id.splitn(3, '-');
+    let started = parts.next()?.parse().ok()?;
+    let source = match parts.nth(1)? {
+        "me" => Some(SourceTag::Me),
+        "remote" => Some(SourceTag::Remote),
+        _ => None,
+    };
+
+    let (sample_rate, channels) = read_format(&mut File::open(path).ok()?).ok()?;
+    let data_bytes = fs::metadata(path).ok()?.len().saturating_sub(WAV_HEADER_LEN);
+    let frames = data_bytes / (channels.max(1) as u64 * 4);
+
+    Some(RecoverableRecording {
+        id: id.to_string(),
+        started,
+        duration_secs: frames as f64 / sample_rate.max(1) as f64,
+        sample_rate,
+        source,
+    })
+}
+
+// A recoverable recording opened to be read back a piece at a time
+pub struct RecoveredRecording {
+    path: PathBuf,
+    file: File,
+    sample_rate: u32,
+    source: Option<SourceTag>,
+    channels: u16,
+    frames: usize,
+}
+
+impl RecoveredRecording {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn source(&self) -> Option<SourceTag> {
+        self.source
+    }
+
+    // Frames in the recording
+    pub fn len(&self) -> usize {
+        self.frames
+    }
+
+    // Interleaved frames `from..to`; session spools are mono, so these are samples
+    pub fn read(&mut self, from: usize, to: usize) -> Result<Vec<f32>, String> {
+        let to = to.min(self.frames);
+        if from >= to {
+            return Ok(Vec::new());
+        }
+
+        let frame_bytes = self.channels as u64 * 4;
+        self.file
+            .seek(SeekFrom::Start(WAV_HEADER_LEN + from as u64 * frame_bytes))
+            .and_then(|_| read_samples(&mut self.file, (to - from) as u64 * frame_bytes))
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))
+    }
+}
+
+// Open a recoverable recording. The data size in the header may be out of date, so
+// everything after it counts.
+pub fn open_recoverable(dir: &Path, id: &str) -> Result<RecoveredRecording, String> {
+    let path = recoverable_path(dir, id)?;
+    let recording = describe(&path, id).ok_or_else(|| format!("{} is not a readable recording", path.display()))?;
+
+    let mut file = File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let (_, channels) = read_format(&mut file).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let channels = channels.max(1);
+    let data_bytes = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0).saturating_sub(WAV_HEADER_LEN);
+
+    Ok(RecoveredRecording {
+        path,
+        file,
+        sample_rate: recording.sample_rate,
+        source: recording.source,
+        channels,
+        frames: (data_bytes / (channels as u64 * 4)) as usize,
+    })
+}
+
+pub fn discard_recoverable(dir: &Path, id: &str) -> Result<(), String> {
+    let path = recoverable_path(dir, id)?;
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete {}: {}", path.display(), e))
+}
+
+fn recoverable_path(dir: &Path, id: &str) -> Result<PathBuf, String> {
+    // Ids come from the frontend; don't let them point outside the spool directory
+    if id.is_empty() || id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid recording id: {}", id));
+    }
+
+    let path = dir.join(format!("{}{}", id, PARTIAL_EXTENSION));
+    if !path.exists() {
+        return Err(format!("No recoverable recording with id {}", id));
+    }
+    Ok(path)
+}
+
+pub fn write_header(writer: &mut impl Write, sample_rate: u32, channels: u16, data_bytes: u64) -> io::Result<()> {
+    let data_bytes = data_bytes.min(u32::MAX as u64 - WAV_HEADER_LEN) as u32;
+    let block_align = channels * 4;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(data_bytes + WAV_HEADER_LEN as u32 - 8).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&32u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_bytes.to_le_bytes())
+}
+
+// Sample rate and channel count from a spool file's header
+pub fn read_format(file: &mut File) -> io::Result<(u32, u16)> {
+    let mut header = [0u8; WAV_HEADER_LEN as usize];
+    file.read_exact(&mut header)?;
+
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WAV file"));
+    }
+    let channels = u16::from_le_bytes([header[22], header[23]]);
+    let sample_rate = u32::from_le_bytes([header[24], header[25], header[26], header[27]]);
+    Ok((sample_rate, channels))
+}
+
+pub fn read_samples(reader: &mut impl Read, bytes: u64) -> io::Result<Vec<f32>> {
+    let mut data = vec![0u8; bytes as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect())
+}
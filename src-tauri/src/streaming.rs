@@ -0,0 +1,320 @@
+// Implementation for streaming transcription while audio is being captured
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use crate::audio::{CaptureSession, SessionId};
+use crate::recorder::{self, RecordingLink, RecordingTarget};
+use crate::resample::{ResampleQuality, Resampler};
+use crate::transcribe::{self, ModelRegistry};
+use crate::transcript::{Segment, SourceTag};
+
+// Sample rate Whisper expects
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+// How often the sliding window is re-transcribed
+const STEP_MS: u64 = 1000;
+
+// Minimum amount of uncommitted audio before running inference
+const MIN_WINDOW_SEC: f32 = 1.0;
+
+// Once the uncommitted window grows past this, everything but the last segment is finalized
+const MAX_WINDOW_SEC: f32 = 10.0;
+
+// Streaming transcriber of each capture session, so both sides of a call can be captioned at once
+static STREAMERS: once_cell::sync::Lazy<Mutex<HashMap<SessionId, Streamer>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct Streamer {
+    is_streaming: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+// Payload for the `caption-partial` event
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialCaption {
+    pub session_id: SessionId,
+    pub source: Option<SourceTag>,
+    pub text: String,
+    // Seconds since the capture started, pauses included, as in `speech-start`/`speech-end`
+    pub start: f32,
+    pub end: f32,
+}
+
+// Payload for the `caption-final` event
+#[derive(Debug, Clone, Serialize)]
+pub struct FinalCaption {
+    pub session_id: SessionId,
+    // Timed like `PartialCaption`
+    pub segment: Segment,
+    pub recording: Option<RecordingLink>,
+}
+
+// Payload for the `caption-error` event
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptionErrorEvent {
+    pub session_id: SessionId,
+    pub message: String,
+}
+
+// Start transcribing the running capture session in sliding windows
+pub fn start_streaming(app: AppHandle, session: Arc<CaptureSession>) -> Result<(), String> {
+    if !session.is_active() {
+        return Err("Not recording".to_string());
+    }
+
+    let mut streamers = STREAMERS.lock().unwrap();
+
+    // If already streaming, return early
+    if streamers.contains_key(&session.id()) {
+        return Err("Already streaming".to_string());
+    }
+
+    // Create the state up front so a missing model is reported to the caller
+    let mut state = app.state::<ModelRegistry>().create_state()?;
+
+    let is_streaming = Arc::new(AtomicBool::new(true));
+    let session_id = session.id();
+    let streaming = is_streaming.clone();
+
+    let thread = thread::spawn(move || {
+        let mut window = SlidingWindow::new(session_id, session.tag(), session.recording().cloned());
+        // Pauses whose audio has been finalized, and those whose gap is in the timeline
+        let mut bridged = 0;
+        let mut gaps = 0;
+
+        // Keep transcribing while both the capture and the streamer are active
+        while streaming.load(Ordering::SeqCst) && session.is_active() {
+            thread::sleep(Duration::from_millis(STEP_MS));
+
+            // Captions end at a pause and pick up after the gap once the session resumes
+            let pauses = session.pauses();
+            for pause in &pauses[bridged..] {
+                window.pull_audio(&session, Some(pause.offset));
+                if let Err(e) = window.bridge_pause(&mut state, &app) {
+                    emit_error(&app, session_id, e);
+                }
+            }
+            bridged = pauses.len();
+            while let Some(duration) = pauses.get(gaps).and_then(|pause| pause.duration) {
+                window.skip_gap(duration);
+                gaps += 1;
+            }
+            if session.is_paused() {
+                continue;
+            }
+
+            window.pull_audio(&session, None);
+            if let Err(e) = window.step(&mut state, &app, false) {
+                emit_error(&app, session_id, e);
+            }
+        }
+
+        // Flush whatever is left once the capture has stopped
+        window.pull_audio(&session, None);
+        window.finish_audio();
+        if let Err(e) = window.step(&mut state, &app, true) {
+            emit_error(&app, session_id, e);
+        }
+
+        streaming.store(false, Ordering::SeqCst);
+    });
+
+    // Store the thread handle
+    streamers.insert(session_id, Streamer { is_streaming, thread });
+
+    Ok(())
+}
+
+// Stop the capture and wait for the streamer to finalize the remaining audio
+pub fn stop_streaming(session: &CaptureSession) -> Result<(), String> {
+    // Stop the cpal stream first so the final pass sees all of the audio.
+    // It may already have stopped on its own after a stream error.
+    let _ = session.stop();
+
+    let streamer = STREAMERS.lock().unwrap().remove(&session.id());
+
+    // Wait for the streaming thread to finish
+    if let Some(streamer) = streamer {
+        streamer.is_streaming.store(false, Ordering::SeqCst);
+        // Ignore any errors from joining the thread
+        let _ = streamer.thread.join();
+    }
+
+    Ok(())
+}
+
+// Audio that has been captured but not yet committed as final captions
+struct SlidingWindow {
+    session_id: SessionId,
+    source: Option<SourceTag>,
+    // Read position in the session's capture buffer
+    read_pos: usize,
+    // Converts the capture stream to 16kHz, created once the capture rate is known
+    resampler: Option<Resampler>,
+    // Resampled 16kHz audio that has not been committed yet
+    pending: Vec<f32>,
+    // Stream time (in seconds) of the first sample in `pending`
+    pending_start: f32,
+    // Where the session's audio will be saved. Saved audio has no gaps, so stream
+    // times are moved back by the pauses skipped so far to place captions in it.
+    recording: Option<RecordingTarget>,
+    skipped: f32,
+    // Id given to the next finalized segment
+    next_segment_id: usize,
+}
+
+impl SlidingWindow {
+    fn new(session_id: SessionId, source: Option<SourceTag>, recording: Option<RecordingTarget>) -> Self {
+        SlidingWindow {
+            session_id,
+            source,
+            read_pos: 0,
+            resampler: None,
+            pending: Vec::new(),
+            pending_start: 0.0,
+            recording,
+            skipped: 0.0,
+            next_segment_id: 0,
+        }
+    }
+
+    // Append newly captured audio to the pending window, up to `until` if given
+    fn pull_audio(&mut self, session: &CaptureSession, until: Option<usize>) {
+        let (samples, sample_rate) = session.samples_since(self.read_pos, until);
+        self.read_pos += samples.len();
+
+        if samples.is_empty() {
+            return;
+        }
+
+        // Keep one resampler for the stream so chunk boundaries don't add artifacts
+        let resampler = self.resampler.get_or_insert_with(|| {
+            Resampler::new(sample_rate, WHISPER_SAMPLE_RATE, ResampleQuality::Balanced)
+        });
+        let resampled = resampler.process(&samples);
+        self.pending.extend_from_slice(&resampled);
+    }
+
+    // Add the audio the resampler holds back for lookahead, at the end of the stream
+    fn finish_audio(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            let remaining = resampler.flush();
+            self.pending.extend_from_slice(&remaining);
+        }
+    }
+
+    // Finalize everything captured before a pause. Audio too short to transcribe
+    // is dropped so the next window starts cleanly after the gap.
+    fn bridge_pause(&mut self, state: &mut whisper_rs::WhisperState, app: &AppHandle) -> Result<(), String> {
+        self.finish_audio();
+        let result = self.step(state, app, true);
+
+        self.pending_start += self.pending.len() as f32 / WHISPER_SAMPLE_RATE as f32;
+        self.pending.clear();
+        self.resampler = None;
+        result
+    }
+
+    // Move the stream time past a pause, so captions keep to the session's clock
+    fn skip_gap(&mut self, seconds: f32) {
+        self.pending_start += seconds;
+        self.skipped += seconds;
+    }
+
+    // Transcribe the pending window, emitting partial captions and committing final ones
+    fn step(&mut self, state: &mut whisper_rs::WhisperState, app: &AppHandle, flush: bool) -> Result<(), String> {
+        let pending_sec = self.pending.len() as f32 / WHISPER_SAMPLE_RATE as f32;
+        if pending_sec < MIN_WINDOW_SEC {
+            return Ok(());
+        }
+
+        let normalized = transcribe::normalize_audio(&self.pending);
+        let segments = transcribe::run_inference(state, &normalized, None)?;
+
+        // Decide how many segments can be committed.
+        // The last segment may still change as more audio arrives, so it is held back
+        // unless the window has grown too long or we are flushing.
+        let commit_count = if flush {
+            segments.len()
+        } else if pending_sec >= MAX_WINDOW_SEC {
+            segments.len().saturating_sub(1).max(1).min(segments.len())
+        } else {
+            0
+        };
+
+        for segment in &segments[..commit_count] {
+            let segment = self.finalize(segment);
+            let recording = self.recording.as_ref().map(|target| target.link(-self.skipped));
+            if let Some(link) = &recording {
+                recorder::add_segments(link, std::slice::from_ref(&segment));
+            }
+            emit_caption(app, "caption-final", FinalCaption { session_id: self.session_id, segment, recording });
+        }
+
+        // Everything after the committed segments is reported as a partial caption
+        let partial: Vec<&str> = segments[commit_count..].iter().map(|segment| segment.text.trim()).collect();
+        let partial_end = segments.last().map(|segment| segment.end).unwrap_or(0.0);
+        let partial_start = segments.get(commit_count).map(|segment| segment.start).unwrap_or(partial_end);
+        emit_caption(app, "caption-partial", PartialCaption {
+            session_id: self.session_id,
+            source: self.source,
+            text: partial.join(" "),
+            start: self.pending_start + partial_start,
+            end: self.pending_start + partial_end,
+        });
+
+        // Drop the committed audio from the window. A long window without any speech is
+        // dropped entirely so silence does not accumulate.
+        let commit_sec = if commit_count == 0 && !(segments.is_empty() && pending_sec >= MAX_WINDOW_SEC) {
+            0.0
+        } else if commit_count < segments.len() {
+            segments[commit_count].start
+        } else {
+            pending_sec
+        };
+        let commit_samples = ((commit_sec * WHISPER_SAMPLE_RATE as f32) as usize).min(self.pending.len());
+
+        if commit_samples > 0 {
+            self.pending.drain(..commit_samples);
+            self.pending_start += commit_samples as f32 / WHISPER_SAMPLE_RATE as f32;
+        }
+
+        Ok(())
+    }
+
+    // Shift a window-relative segment to stream time and give it a session-wide id
+    fn finalize(&mut self, segment: &Segment) -> Segment {
+        let offset = self.pending_start;
+        let mut segment = segment.clone();
+
+        segment.id = self.next_segment_id;
+        segment.source = self.source;
+        segment.start += offset;
+        segment.end += offset;
+        for word in &mut segment.words {
+            word.start += offset;
+            word.end += offset;
+        }
+
+        self.next_segment_id += 1;
+        segment
+    }
+}
+
+fn emit_caption<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    if let Err(e) = app.emit(event, payload) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
+
+fn emit_error(app: &AppHandle, session_id: SessionId, message: String) {
+    eprintln!("Streaming transcription error: {}", message);
+    if let Err(e) = app.emit("caption-error", CaptionErrorEvent { session_id, message }) {
+        eprintln!("Failed to emit caption-error: {}", e);
+    }
+}
@@ -1,13 +1,44 @@
 // Implementation for transcription using whisper-rs 0.13.2
 
-use whisper_rs::{WhisperContext, FullParams, WhisperContextParameters, SamplingStrategy};
-use std::path::Path;
-use crate::diarize;
+use whisper_rs::{WhisperContext, WhisperState, FullParams, WhisperContextParameters, SamplingStrategy, DtwParameters, DtwMode, DtwModelPreset, WhisperSysContext, WhisperSysState};
+use std::path::{Path, PathBuf};
+use std::ffi::{c_int, c_void};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::diarize::{self, ClusteringOptions, Diarizer};
+use crate::models;
+use crate::transcript::{mean_probability, Segment, Transcript, Word};
 
-pub fn transcribe(samples: &[f32]) -> Result<String, String> {
+// Language Whisper is told the audio is in; it is not detected
+const LANGUAGE: &str = "en";
+
+pub fn transcribe(models: &ModelRegistry, samples: &[f32], control: Option<&InferenceControl>) -> Result<Transcript, String> {
     // Check if we have enough audio data
+    if let Some(transcript) = check_audio(samples) {
+        return Ok(transcript);
+    }
+    
+    let transcript = transcribe_segments(models, samples, control)?;
+    
+    // Log every segment
+    for segment in &transcript.segments {
+        println!("Segment {}: [{:.2}-{:.2}] {}", segment.id, segment.start, segment.end, segment.text);
+    }
+    
+    // If no text was transcribed, provide a helpful message
+    if transcript.text().is_empty() {
+        println!("No speech detected in the audio");
+        return Ok(Transcript::with_notice(transcript.duration, "No speech detected in the audio."));
+    }
+    
+    println!("Final transcript: {}", transcript.text());
+    Ok(transcript)
+}
+
+// Returns an empty transcript with a message for the user if the audio cannot be transcribed
+fn check_audio(samples: &[f32]) -> Option<Transcript> {
     if samples.is_empty() {
-        return Ok("No audio data received.".to_string());
+        return Some(Transcript::with_notice(0.0, "No audio data received."));
     }
     
     println!("Transcribing {} samples of audio data", samples.len());
@@ -19,36 +50,180 @@ pub fn transcribe(samples: &[f32]) -> Result<String, String> {
     // If audio is too short, return early
     if duration_sec < 0.5 {
         println!("Audio too short for reliable transcription");
-        return Ok("Audio too short for reliable transcription.".to_string());
+        return Some(Transcript::with_notice(duration_sec, "Audio too short for reliable transcription."));
+    }
+    
+    None
+}
+
+// Run Whisper on 16kHz samples using the shared model
+fn transcribe_segments(models: &ModelRegistry, samples: &[f32], control: Option<&InferenceControl>) -> Result<Transcript, String> {
+    // Create state for inference on the shared model
+    let mut state = models.create_state()?;
+    
+    // Normalize the audio to ensure it's within the expected range
+    let normalized_samples = normalize_audio(samples);
+    
+    // Calculate and print audio statistics
+    let rms = calculate_rms(&normalized_samples);
+    println!("Audio RMS: {:.6}", rms);
+    
+    // Run inference
+    println!("Running inference on audio...");
+    let segments = run_inference(&mut state, &normalized_samples, control)?;
+    
+    println!("Transcription produced {} segments", segments.len());
+    
+    Ok(Transcript {
+        language: LANGUAGE.to_string(),
+        duration: samples.len() as f32 / 16000.0,
+        segments,
+        speakers: Vec::new(),
+        notice: None,
+    })
+}
+
+// A Whisper model that has been loaded into memory
+#[derive(Clone)]
+pub struct LoadedModel {
+    pub path: PathBuf,
+    pub context: Arc<WhisperContext>,
+}
+
+// Registry holding the shared Whisper context.
+// The model is loaded once and every transcription job creates its own `WhisperState`
+// from it. States keep the underlying context alive, so swapping the model while a job
+// is running is safe: the running job finishes on the old model.
+pub struct ModelRegistry {
+    active: RwLock<Option<LoadedModel>>,
+    // Model loaded on first use if none has been selected
+    default_path: PathBuf,
+    // Held while loading the default model, so callers racing to use it load it once
+    first_load: Mutex<()>,
+}
+
+impl ModelRegistry {
+    pub fn new(default_path: PathBuf) -> Self {
+        ModelRegistry {
+            active: RwLock::new(None),
+            default_path,
+            first_load: Mutex::new(()),
+        }
+    }
+    
+    // Load a model from disk and make it the active one
+    pub fn load(&self, model_path: &Path) -> Result<LoadedModel, String> {
+        // Load outside the lock so running jobs are not blocked while reading the file
+        let model = LoadedModel {
+            path: model_path.to_path_buf(),
+            context: Arc::new(load_context(model_path)?),
+        };
+        
+        println!("Loaded Whisper model: {}", model_path.display());
+        *self.active.write().unwrap() = Some(model.clone());
+        
+        Ok(model)
+    }
+    
+    // Get the active model, loading the default model on first use
+    pub fn active(&self) -> Result<LoadedModel, String> {
+        if let Some(model) = self.active.read().unwrap().as_ref() {
+            return Ok(model.clone());
+        }
+        
+        // Another caller may have loaded it while this one waited
+        let _loading = self.first_load.lock().unwrap();
+        if let Some(model) = self.active.read().unwrap().as_ref() {
+            return Ok(model.clone());
+        }
+        
+        self.load(&self.default_path)
     }
     
+    // Path of the active model, if one has been loaded
+    pub fn active_path(&self) -> Option<PathBuf> {
+        self.active.read().unwrap().as_ref().map(|model| model.path.clone())
+    }
+    
+    // Create a fresh inference state on the active model
+    pub fn create_state(&self) -> Result<WhisperState, String> {
+        self.active()?
+            .context
+            .create_state()
+            .map_err(|e| format!("Failed to create state: {}", e))
+    }
+}
+
+// Load a Whisper model from disk
+fn load_context(model_path: &Path) -> Result<WhisperContext, String> {
     // Create context parameters with default settings
-    let params = WhisperContextParameters::default();
+    let mut params = WhisperContextParameters::default();
+    
+    // Enable DTW token alignment when the model matches one of the known presets
+    if let Some(model_preset) = dtw_preset(model_path) {
+        params.dtw_parameters(DtwParameters {
+            mode: DtwMode::ModelPreset { model_preset },
+            ..DtwParameters::default()
+        });
+    }
     
     // Check if the model file exists
-    let model_path = "whisper-small.bin";
-    if !Path::new(model_path).exists() {
+    if !model_path.exists() {
         return Err(format!(
             "Model file '{}' not found. Please download it using one of the following methods:\n\n\
             1. Download from Hugging Face: https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin\n\
             2. Or use the following command in your terminal:\n\
-               wget https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin -O whisper-small.bin\n\n\
-            After downloading, place the file in the application directory: {}",
-            model_path,
-            std::env::current_dir().unwrap_or_default().display()
+               wget https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin -O ggml-small.bin\n\n\
+            After downloading, place the file at: {}",
+            model_path.display(),
+            model_path.display()
         ));
     }
     
+    let model_path_str = model_path
+        .to_str()
+        .ok_or_else(|| format!("Model path is not valid UTF-8: {}", model_path.display()))?;
+    
     // Create context from model file
-    let ctx = WhisperContext::new_with_params(
-        model_path, // Use string path
+    WhisperContext::new_with_params(
+        model_path_str,
         params
-    ).map_err(|e| format!("Failed to load model: {}", e))?;
+    ).map_err(|e| format!("Failed to load model: {}", e))
+}
+
+// Pick the DTW alignment heads for a model based on its ggml header
+fn dtw_preset(model_path: &Path) -> Option<DtwModelPreset> {
+    let header = models::read_header(model_path).ok()?;
+    let multilingual = header.is_multilingual();
     
-    // Create state for inference
-    let mut state = ctx.create_state()
-        .map_err(|e| format!("Failed to create state: {}", e))?;
+    let preset = match (header.model_type(), multilingual) {
+        ("tiny", false) => DtwModelPreset::TinyEn,
+        ("tiny", true) => DtwModelPreset::Tiny,
+        ("base", false) => DtwModelPreset::BaseEn,
+        ("base", true) => DtwModelPreset::Base,
+        ("small", false) => DtwModelPreset::SmallEn,
+        ("small", true) => DtwModelPreset::Small,
+        ("medium", false) => DtwModelPreset::MediumEn,
+        ("medium", true) => DtwModelPreset::Medium,
+        ("large", _) => {
+            // large-v3 is the only large model with 128 mel bins; v1 and v2 are told apart by name
+            let file_name = model_path.file_name()?.to_string_lossy().to_lowercase();
+            if header.n_mels == 128 {
+                DtwModelPreset::LargeV3
+            } else if file_name.contains("v1") {
+                DtwModelPreset::LargeV1
+            } else {
+                DtwModelPreset::LargeV2
+            }
+        }
+        _ => return None,
+    };
     
+    Some(preset)
+}
+
+// Build the inference parameters shared by batch and streaming transcription
+pub fn build_params<'a, 'b>() -> FullParams<'a, 'b> {
     // Create parameters with default settings
     let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
     
@@ -58,28 +233,67 @@ pub fn transcribe(samples: &[f32]) -> Result<String, String> {
     params.set_print_progress(false);
     params.set_print_realtime(false);
     params.set_print_timestamps(true); // Enable timestamps for diarization
-    params.set_language(Some("en")); // Force English language
+    params.set_token_timestamps(true); // Per-token timestamps for word timing
+    params.set_language(Some(LANGUAGE)); // Force English language
     
-    // Normalize the audio to ensure it's within the expected range
-    let normalized_samples = normalize_audio(samples);
+    params
+}
+
+// Progress and cancellation hooks for a single inference run
+pub struct InferenceControl<'a> {
+    // Called with the progress in percent
+    pub on_progress: &'a (dyn Fn(i32) + Sync),
+    // Inference is aborted as soon as this is set
+    pub cancelled: &'a AtomicBool,
+}
+
+unsafe extern "C" fn progress_trampoline(
+    _ctx: *mut WhisperSysContext,
+    _state: *mut WhisperSysState,
+    progress: c_int,
+    user_data: *mut c_void,
+) {
+    // SAFETY: user_data points to the `InferenceControl` borrowed for the duration of `state.full`
+    let control = &*(user_data as *const InferenceControl);
+    (control.on_progress)(progress);
+}
+
+unsafe extern "C" fn abort_trampoline(user_data: *mut c_void) -> bool {
+    // SAFETY: user_data points to the `InferenceControl` borrowed for the duration of `state.full`
+    let control = &*(user_data as *const InferenceControl);
+    control.cancelled.load(Ordering::SeqCst)
+}
+
+// Run inference on 16kHz samples and return each segment with its words
+pub fn run_inference(state: &mut WhisperState, samples: &[f32], control: Option<&InferenceControl>) -> Result<Vec<Segment>, String> {
+    let mut params = build_params();
     
-    // Calculate and print audio statistics
-    let rms = calculate_rms(&normalized_samples);
-    println!("Audio RMS: {:.6}", rms);
+    // The safe closure setters in whisper-rs either leak or keep a pointer to a moved
+    // closure, so the callbacks are wired up directly. `control` outlives `state.full`,
+    // which is the only place the callbacks are invoked.
+    if let Some(control) = control {
+        let user_data = control as *const InferenceControl as *mut c_void;
+        unsafe {
+            params.set_progress_callback(Some(progress_trampoline));
+            params.set_progress_callback_user_data(user_data);
+            params.set_abort_callback(Some(abort_trampoline));
+            params.set_abort_callback_user_data(user_data);
+        }
+    }
     
-    // Run inference
-    println!("Running inference on audio...");
-    state.full(params, &normalized_samples)
-        .map_err(|e| format!("Failed to run inference: {}", e))?;
+    let result = state.full(params, samples);
+    
+    if control.map(|control| control.cancelled.load(Ordering::SeqCst)).unwrap_or(false) {
+        return Err("Transcription cancelled".to_string());
+    }
+    
+    result.map_err(|e| format!("Failed to run inference: {}", e))?;
     
     // Get number of segments
     let num_segments = state.full_n_segments()
         .map_err(|e| format!("Failed to get number of segments: {}", e))?;
     
-    println!("Transcription produced {} segments", num_segments);
-    
-    let mut transcript = String::new();
-    let mut timestamps = Vec::new();
+    let mut segments = Vec::new();
     
     // Iterate through segments and collect text with timestamps
     for i in 0..num_segments {
@@ -92,81 +306,147 @@ pub fn transcribe(samples: &[f32]) -> Result<String, String> {
         let end_time = state.full_get_segment_t1(i)
             .map_err(|e| format!("Failed to get segment end time: {}", e))? as f32 / 100.0;
         
-        println!("Segment {}: [{:.2}-{:.2}] {}", i, start_time, end_time, segment_text);
-        transcript.push_str(&segment_text);
-        transcript.push(' ');
+        let words = extract_words(state, i, start_time, end_time)?;
         
-        timestamps.push((start_time, end_time));
-    }
-    
-    // If no text was transcribed, provide a helpful message
-    if transcript.trim().is_empty() {
-        println!("No speech detected in the audio");
-        return Ok("No speech detected in the audio.".to_string());
+        segments.push(Segment {
+            id: i as usize,
+            start: start_time,
+            end: end_time,
+            text: segment_text.trim().to_string(),
+            confidence: mean_probability(&words),
+            words,
+            speaker_id: None,
+            source: None,
+        });
     }
     
-    println!("Final transcript: {}", transcript);
-    Ok(transcript)
+    Ok(segments)
 }
 
-// Function to transcribe with diarization
-pub fn transcribe_with_diarization(samples: &[f32]) -> Result<String, String> {
-    // First, perform regular transcription
-    let transcript = transcribe(samples)?;
+// Tokens that make up a single word
+struct TokenGroup {
+    text: String,
+    start: f32,
+    end: f32,
+    dtw_start: Option<f32>,
+    probabilities: Vec<f32>,
+}
+
+// Group the tokens of a segment into words.
+// A token starting with a space begins a new word; punctuation and word pieces are
+// appended to the current one. DTW timestamps are used for word starts when available,
+// otherwise the token-level t0/t1 from the timestamp decoder.
+fn extract_words(state: &WhisperState, segment: i32, segment_start: f32, segment_end: f32) -> Result<Vec<Word>, String> {
+    let num_tokens = state.full_n_tokens(segment)
+        .map_err(|e| format!("Failed to get number of tokens: {}", e))?;
     
-    // Get timestamps from Whisper
-    let timestamps = extract_word_timestamps(samples)?;
+    let mut words: Vec<TokenGroup> = Vec::new();
     
-    // If we couldn't get proper timestamps, create approximate ones
-    let timestamps = if timestamps.is_empty() {
-        // Create approximate timestamps
-        let words: Vec<&str> = transcript.split_whitespace().collect();
-        let duration = samples.len() as f32 / 16000.0;
+    for t in 0..num_tokens {
+        let text = state.full_get_token_text_lossy(segment, t)
+            .map_err(|e| format!("Failed to get token text: {}", e))?;
+        
+        // Skip special tokens such as [_BEG_] and timestamp markers
+        if text.starts_with("[_") || text.starts_with("<|") {
+            continue;
+        }
         
-        // More sophisticated time distribution - allocate time based on word length
-        let total_chars = words.iter().map(|w| w.len()).sum::<usize>() as f32;
-        let time_per_char = duration / total_chars;
+        let data = state.full_get_token_data(segment, t)
+            .map_err(|e| format!("Failed to get token data: {}", e))?;
         
-        let mut current_time = 0.0;
-        let mut word_timestamps = Vec::new();
+        let t0 = (data.t0 as f32 / 100.0).clamp(segment_start, segment_end);
+        let t1 = (data.t1 as f32 / 100.0).clamp(t0, segment_end);
+        let t_dtw = if data.t_dtw >= 0 { Some(data.t_dtw as f32 / 100.0) } else { None };
         
-        for word in &words {
-            let word_duration = word.len() as f32 * time_per_char;
-            let start_time = current_time;
-            let end_time = current_time + word_duration;
-            
-            word_timestamps.push((start_time, end_time));
-            current_time = end_time;
+        match words.last_mut() {
+            Some(word) if !text.starts_with(' ') => {
+                word.text.push_str(&text);
+                word.end = t1;
+                word.probabilities.push(data.p);
+            }
+            _ => {
+                if text.trim().is_empty() {
+                    continue;
+                }
+                words.push(TokenGroup {
+                    text: text.trim_start().to_string(),
+                    start: t0,
+                    end: t1,
+                    dtw_start: t_dtw,
+                    probabilities: vec![data.p],
+                });
+            }
         }
+    }
+    
+    let dtw_available = !words.is_empty() && words.iter().all(|word| word.dtw_start.is_some());
+    
+    let mut result: Vec<Word> = Vec::with_capacity(words.len());
+    for (i, word) in words.iter().enumerate() {
+        // With DTW, each word runs from its aligned start to the next word's aligned start
+        let (start, end) = match word.dtw_start {
+            Some(dtw_start) if dtw_available => {
+                let start = dtw_start.clamp(segment_start, segment_end);
+                let end = words.get(i + 1)
+                    .and_then(|next| next.dtw_start)
+                    .unwrap_or(segment_end)
+                    .clamp(start, segment_end);
+                (start, end)
+            }
+            _ => (word.start, word.end),
+        };
         
-        word_timestamps
-    } else {
-        timestamps
-    };
+        let probability = word.probabilities.iter().sum::<f32>() / word.probabilities.len() as f32;
+        
+        result.push(Word {
+            text: word.text.clone(),
+            start,
+            end,
+            probability,
+        });
+    }
+    
+    Ok(result)
+}
+
+// Function to transcribe with diarization
+pub fn transcribe_with_diarization(
+    models: &ModelRegistry,
+    samples: &[f32],
+    diarizer: &dyn Diarizer,
+    options: &ClusteringOptions,
+    control: Option<&InferenceControl>,
+) -> Result<Transcript, String> {
+    // Check if we have enough audio data
+    if let Some(transcript) = check_audio(samples) {
+        return Ok(transcript);
+    }
+    
+    // First, perform regular transcription
+    let mut transcript = transcribe_segments(models, samples, control)?;
+    
+    if transcript.segments.iter().all(|segment| segment.words.is_empty()) {
+        println!("No speech detected in the audio");
+        return Ok(Transcript::with_notice(transcript.duration, "No speech detected in the audio."));
+    }
     
     // Then, perform diarization
-    let diarization_result = diarize::diarize(samples, 16000);
+    println!("Diarizing with the {} backend", diarizer.name());
+    let diarization_result = diarizer.diarize(samples, 16000, options)?;
     
-    // Combine transcription with diarization
-    let diarized_transcript = diarize::combine_with_transcription(
+    // Split the segments at speaker turns using the word timings from Whisper
+    let (segments, speakers) = diarize::combine_with_transcription(
         &diarization_result,
-        &transcript,
-        &timestamps
+        &transcript.segments
     );
+    transcript.segments = segments;
+    transcript.speakers = speakers;
     
-    Ok(diarized_transcript)
-}
-
-// Extract word timestamps from audio using Whisper
-// This is a placeholder - in a real implementation, you would get these from Whisper
-fn extract_word_timestamps(_samples: &[f32]) -> Result<Vec<(f32, f32)>, String> {
-    // In a real implementation, you would extract these from Whisper's output
-    // For now, we'll return an empty vector to trigger the fallback mechanism
-    Ok(Vec::new())
+    Ok(transcript)
 }
 
 // Function to normalize audio to ensure it's within the expected range
-fn normalize_audio(samples: &[f32]) -> Vec<f32> {
+pub fn normalize_audio(samples: &[f32]) -> Vec<f32> {
     // Find the maximum absolute value in the samples
     let max_abs = samples.iter()
         .map(|&s| s.abs())
@@ -0,0 +1,108 @@
+// Structured transcript data shared by the commands, the UI and exporters
+
+use serde::{Deserialize, Serialize};
+
+// A single word with timing taken from Whisper's token timestamps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Word {
+    pub text: String,
+    pub start: f32,
+    pub end: f32,
+    pub probability: f32,
+}
+
+// A contiguous piece of speech, optionally attributed to a speaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub id: usize,
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+    pub words: Vec<Word>,
+    // Mean probability of the words in the segment
+    pub confidence: f32,
+    pub speaker_id: Option<String>,
+    // Which side of a call the segment was captured from, when both are captured
+    pub source: Option<SourceTag>,
+}
+
+impl Segment {
+    // Build a segment from its words, deriving text, timing and confidence
+    pub fn from_words(id: usize, words: Vec<Word>, speaker_id: Option<String>) -> Self {
+        let text = words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ");
+        let start = words.first().map(|word| word.start).unwrap_or(0.0);
+        let end = words.last().map(|word| word.end).unwrap_or(start);
+
+        Segment {
+            id,
+            start,
+            end,
+            text,
+            confidence: mean_probability(&words),
+            words,
+            speaker_id,
+            source: None,
+        }
+    }
+}
+
+// Capture source of a segment in a call: the local microphone or the system audio
+// carrying the other participants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceTag {
+    Me,
+    Remote,
+}
+
+// A speaker that appears in the transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Speaker {
+    pub id: String,
+    pub name: String,
+    // How confidently segments were attributed to this speaker, from 0 to 1
+    pub confidence: Option<f32>,
+}
+
+// The result of transcribing a recording
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    // Language code the audio was transcribed as, e.g. "en"; set by configuration, not detected
+    pub language: String,
+    // Duration of the transcribed audio in seconds
+    pub duration: f32,
+    pub segments: Vec<Segment>,
+    pub speakers: Vec<Speaker>,
+    // Message for the user when nothing could be transcribed
+    pub notice: Option<String>,
+}
+
+impl Transcript {
+    // An empty transcript that only carries a message for the user
+    pub fn with_notice(duration: f32, notice: &str) -> Self {
+        Transcript {
+            duration,
+            notice: Some(notice.to_string()),
+            ..Transcript::default()
+        }
+    }
+
+    // Plain text of the whole transcript
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| segment.text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// Mean probability of a list of words, or 0 if there are none
+pub fn mean_probability(words: &[Word]) -> f32 {
+    if words.is_empty() {
+        return 0.0;
+    }
+
+    words.iter().map(|word| word.probability).sum::<f32>() / words.len() as f32
+}
@@ -0,0 +1,501 @@
+// Voice activity detection on the capture stream.
+// The detector runs on each session's recording thread; its speech-start/speech-end
+// events are handled on a separate thread, which forwards them to the frontend
+// and commits finished utterances for transcription while segmenting.
+
+use once_cell::sync::{Lazy, OnceCell};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager};
+use crate::audio::{CaptureSession, CaptureSessions, SessionId};
+use crate::jobs::TranscriptionQueue;
+use crate::resample::{ResampleQuality, Resampler};
+
+// The detector works on 16kHz mono audio
+const VAD_SAMPLE_RATE: u32 = 16000;
+
+// Frame length of the energy classifier (30ms)
+const ENERGY_FRAME_LEN: usize = 480;
+
+// Consecutive speech needed to start an utterance, and silence needed to end one
+const SPEECH_START_MS: usize = 90;
+const SPEECH_END_MS: usize = 700;
+
+// Utterances shorter than this are treated as noise and not committed on their own
+const MIN_SPEECH_MS: usize = 250;
+
+// Speech probability above which a frame counts as speech
+const SPEECH_PROBABILITY: f32 = 0.5;
+
+// A frame must be this far above the noise floor to be speech
+const ENERGY_MARGIN_DB: f32 = 9.0;
+
+// Frames quieter than this are never speech, whatever the noise floor
+const MIN_SPEECH_DB: f32 = -55.0;
+
+// Speech is tonal; noise has a flat spectrum (flatness near 1)
+const FLATNESS_THRESHOLD: f32 = 0.35;
+
+// Frequency band used for spectral flatness
+const FLATNESS_MIN_HZ: f32 = 300.0;
+const FLATNESS_MAX_HZ: f32 = 4000.0;
+
+// File name of the optional Silero VAD model inside the models directory
+#[cfg(feature = "onnx")]
+pub const SILERO_MODEL_FILE: &str = "silero_vad.onnx";
+
+// App handle and models directory, set once at startup
+static APP: OnceCell<AppHandle> = OnceCell::new();
+static MODELS_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+// Sender for events raised on the recording threads, tagged with their session
+static EVENTS: OnceCell<Mutex<Sender<(SessionId, VadEvent)>>> = OnceCell::new();
+
+// Segmentation state of each capture session whose utterances the backend commits for transcription.
+// Each has its own lock, held while a commit reads and queues audio, so other sessions aren't held up.
+static SEGMENTERS: Lazy<Mutex<HashMap<SessionId, Arc<Mutex<Segmenter>>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Voice activity change. Times are in seconds since the capture started, counting
+// the time spent paused, so they line up with captions and recording links;
+// `offset` is a position in the session's capture buffer, which has no gaps.
+#[derive(Debug, Clone)]
+pub enum VadEvent {
+    SpeechStart { time: f32 },
+    SpeechEnd { offset: usize, start: f32, end: f32 },
+}
+
+// Payload for the `speech-start` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeechStartEvent {
+    pub session_id: SessionId,
+    // Seconds since the capture started, pauses included
+    pub time: f32,
+}
+
+// Payload for the `speech-end` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeechEndEvent {
+    pub session_id: SessionId,
+    // Seconds since the capture started, pauses included
+    pub start: f32,
+    pub end: f32,
+}
+
+struct Segmenter {
+    diarize: bool,
+    // Capture buffer offset up to which audio has been committed
+    committed: usize,
+    // Cleared once segmenting stops, for a commit that was about to start
+    active: bool,
+}
+
+// Start the event thread; called once from the app setup
+pub fn init(app: AppHandle, models_dir: PathBuf) {
+    let (sender, receiver) = mpsc::channel();
+
+    let _ = APP.set(app);
+    let _ = MODELS_DIR.set(models_dir);
+    let _ = EVENTS.set(Mutex::new(sender));
+
+    thread::spawn(move || run_events(receiver));
+}
+
+// Hand events from a session's recording thread to the event thread
+pub fn dispatch(session_id: SessionId, events: Vec<VadEvent>) {
+    if let Some(sender) = EVENTS.get() {
+        let sender = sender.lock().unwrap();
+        for event in events {
+            let _ = sender.send((session_id, event));
+        }
+    }
+}
+
+// Commit each utterance of the session as a transcription job as soon as it ends
+pub fn start_segmenting(session: &CaptureSession, diarize: bool) -> Result<(), String> {
+    if !session.is_active() {
+        return Err("Not recording".to_string());
+    }
+
+    let segmenter = Segmenter {
+        diarize,
+        committed: 0,
+        active: true,
+    };
+    SEGMENTERS.lock().unwrap().insert(session.id(), Arc::new(Mutex::new(segmenter)));
+    println!("Segmenting speech of capture {} for transcription (diarize: {})", session.id(), diarize);
+    Ok(())
+}
+
+// Stop segmenting the session and return the offset of the first audio not yet committed
+pub fn stop_segmenting(session_id: SessionId) -> usize {
+    let Some(segmenter) = SEGMENTERS.lock().unwrap().remove(&session_id) else {
+        return 0;
+    };
+
+    // Waits for a commit in progress, so the rest of the recording is queued after it
+    let mut segmenter = segmenter.lock().unwrap();
+    segmenter.active = false;
+    segmenter.committed
+}
+
+fn run_events(receiver: Receiver<(SessionId, VadEvent)>) {
+    for (session_id, event) in receiver {
+        let Some(app) = APP.get() else { continue };
+
+        match event {
+            VadEvent::SpeechStart { time } => {
+                emit(app, "speech-start", SpeechStartEvent { session_id, time });
+            }
+            VadEvent::SpeechEnd { offset, start, end } => {
+                emit(app, "speech-end", SpeechEndEvent { session_id, start, end });
+                commit_segment(app, session_id, offset, start, end);
+            }
+        }
+    }
+}
+
+// Queue the session's audio up to `offset` for transcription if it is being segmented.
+// The session's segmenter stays locked while queueing so a final stop can't overtake the commit.
+fn commit_segment(app: &AppHandle, session_id: SessionId, offset: usize, start: f32, end: f32) {
+    let Some(segmenter) = SEGMENTERS.lock().unwrap().get(&session_id).cloned() else { return };
+    let mut segmenter = segmenter.lock().unwrap();
+    let Ok(session) = app.state::<CaptureSessions>().get(session_id) else { return };
+
+    // Short bursts are left to be committed with the next utterance
+    if !segmenter.active || offset <= segmenter.committed || end - start < MIN_SPEECH_MS as f32 / 1000.0 {
+        return;
+    }
+
+    let (samples, at) = session.process_samples(segmenter.committed, Some(offset));
+    segmenter.committed = offset;
+
+    let recording = session.recording().map(|target| target.link(at));
+    let queue = app.state::<TranscriptionQueue>();
+    if let Err(e) = queue.enqueue(app, samples, segmenter.diarize, false, session.tag(), recording) {
+        eprintln!("Failed to queue speech segment: {}", e);
+    }
+}
+
+fn emit<T: Serialize + Clone>(app: &AppHandle, event: &str, payload: T) {
+    if let Err(e) = app.emit(event, payload) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
+
+// Scores frames of 16kHz audio
+trait SpeechClassifier: Send {
+    // Samples per frame
+    fn frame_len(&self) -> usize;
+
+    // Probability that the frame contains speech
+    fn speech_probability(&mut self, frame: &[f32]) -> f32;
+}
+
+// Classifies frames by their level above an adaptive noise floor and by spectral flatness
+struct EnergyClassifier {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    // FFT bins used for spectral flatness
+    flatness_bins: std::ops::Range<usize>,
+    noise_floor_db: Option<f32>,
+}
+
+impl EnergyClassifier {
+    fn new() -> Self {
+        let n_fft = ENERGY_FRAME_LEN.next_power_of_two();
+        let bin_hz = VAD_SAMPLE_RATE as f32 / n_fft as f32;
+
+        // Hann window
+        let window = (0..ENERGY_FRAME_LEN)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (ENERGY_FRAME_LEN - 1) as f32).cos())
+            .collect();
+
+        EnergyClassifier {
+            fft: FftPlanner::new().plan_fft_forward(n_fft),
+            window,
+            buffer: vec![Complex::new(0.0, 0.0); n_fft],
+            flatness_bins: (FLATNESS_MIN_HZ / bin_hz) as usize..(FLATNESS_MAX_HZ / bin_hz) as usize,
+            noise_floor_db: None,
+        }
+    }
+
+    // Geometric over arithmetic mean of the power spectrum
+    fn spectral_flatness(&mut self, frame: &[f32]) -> f32 {
+        for (i, value) in self.buffer.iter_mut().enumerate() {
+            let sample = frame.get(i).zip(self.window.get(i)).map(|(s, w)| s * w).unwrap_or(0.0);
+            *value = Complex::new(sample, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        let power: Vec<f32> = self.buffer[self.flatness_bins.clone()]
+            .iter()
+            .map(|c| c.norm_sqr() + 1e-12)
+            .collect();
+
+        let log_mean = power.iter().map(|p| p.ln()).sum::<f32>() / power.len() as f32;
+        let mean = power.iter().sum::<f32>() / power.len() as f32;
+
+        log_mean.exp() / mean
+    }
+}
+
+impl SpeechClassifier for EnergyClassifier {
+    fn frame_len(&self) -> usize {
+        ENERGY_FRAME_LEN
+    }
+
+    fn speech_probability(&mut self, frame: &[f32]) -> f32 {
+        let energy = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+        let energy_db = 10.0 * (energy + 1e-10).log10();
+
+        // The noise floor follows quiet frames quickly and loud frames slowly
+        let floor = self.noise_floor_db.get_or_insert(energy_db);
+        let rate = if energy_db < *floor { 0.2 } else { 0.005 };
+        *floor += rate * (energy_db - *floor);
+        let floor = *floor;
+
+        if energy_db < MIN_SPEECH_DB || energy_db - floor < ENERGY_MARGIN_DB {
+            return 0.0;
+        }
+
+        if self.spectral_flatness(frame) < FLATNESS_THRESHOLD {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+// Silero VAD (v5) ONNX model
+#[cfg(feature = "onnx")]
+struct SileroClassifier {
+    session: Arc<Mutex<ort::session::Session>>,
+    // Recurrent state carried between frames
+    state: Vec<f32>,
+    // Last samples of the previous frame, prepended to the next one
+    context: Vec<f32>,
+}
+
+#[cfg(feature = "onnx")]
+const SILERO_FRAME_LEN: usize = 512;
+
+#[cfg(feature = "onnx")]
+const SILERO_CONTEXT_LEN: usize = 64;
+
+#[cfg(feature = "onnx")]
+const SILERO_STATE_LEN: usize = 2 * 128;
+
+#[cfg(feature = "onnx")]
+static SILERO: OnceCell<Option<Arc<Mutex<ort::session::Session>>>> = OnceCell::new();
+
+#[cfg(feature = "onnx")]
+impl SileroClassifier {
+    // Load the model on first use, if it is installed
+    fn load() -> Option<Self> {
+        let session = SILERO
+            .get_or_init(|| {
+                let path = MODELS_DIR.get()?.join(SILERO_MODEL_FILE);
+                if !path.is_file() {
+                    return None;
+                }
+
+                match ort::session::Session::builder().and_then(|builder| builder.commit_from_file(&path)) {
+                    Ok(session) => {
+                        println!("Loaded Silero VAD model {}", path.display());
+                        Some(Arc::new(Mutex::new(session)))
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to load Silero VAD model {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            })
+            .clone()?;
+
+        Some(SileroClassifier {
+            session,
+            state: vec![0.0; SILERO_STATE_LEN],
+            context: vec![0.0; SILERO_CONTEXT_LEN],
+        })
+    }
+
+    fn run(&mut self, frame: &[f32]) -> Result<f32, String> {
+        use ort::value::Tensor;
+
+        let mut input = self.context.clone();
+        input.extend_from_slice(frame);
+        self.context = frame[frame.len() - SILERO_CONTEXT_LEN..].to_vec();
+
+        let to_err = |e: ort::Error| format!("Silero VAD failed: {}", e);
+        let input = Tensor::from_array(([1usize, input.len()], input)).map_err(to_err)?;
+        let state = Tensor::from_array(([2usize, 1, 128], self.state.clone())).map_err(to_err)?;
+        let sr = Tensor::from_array(((), vec![VAD_SAMPLE_RATE as i64])).map_err(to_err)?;
+
+        let mut session = self.session.lock().unwrap();
+        let outputs = session
+            .run(ort::inputs!["input" => input, "state" => state, "sr" => sr])
+            .map_err(to_err)?;
+
+        let (_, probability) = outputs["output"].try_extract_tensor::<f32>().map_err(to_err)?;
+        let (_, state) = outputs["stateN"].try_extract_tensor::<f32>().map_err(to_err)?;
+        self.state = state.to_vec();
+
+        Ok(probability.first().copied().unwrap_or(0.0))
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl SpeechClassifier for SileroClassifier {
+    fn frame_len(&self) -> usize {
+        SILERO_FRAME_LEN
+    }
+
+    fn speech_probability(&mut self, frame: &[f32]) -> f32 {
+        self.run(frame).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            0.0
+        })
+    }
+}
+
+// Tracks speech in the mono capture stream. Fed from the recording thread.
+pub struct VoiceActivityDetector {
+    classifier: Box<dyn SpeechClassifier>,
+    // Device samples per 16kHz sample
+    step: f64,
+    resampler: Resampler,
+    // 16kHz samples waiting for a full frame
+    pending: Vec<f32>,
+    // 16kHz samples classified so far
+    processed: usize,
+    in_speech: bool,
+    speech_frames: usize,
+    silence_frames: usize,
+    speech_start: usize,
+    // Seconds the capture spent paused so far
+    gaps: f32,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(sample_rate: u32) -> Self {
+        #[cfg(feature = "onnx")]
+        let classifier: Box<dyn SpeechClassifier> = match SileroClassifier::load() {
+            Some(silero) => Box::new(silero),
+            None => Box::new(EnergyClassifier::new()),
+        };
+        #[cfg(not(feature = "onnx"))]
+        let classifier: Box<dyn SpeechClassifier> = Box::new(EnergyClassifier::new());
+
+        VoiceActivityDetector {
+            classifier,
+            step: sample_rate as f64 / VAD_SAMPLE_RATE as f64,
+            resampler: Resampler::new(sample_rate, VAD_SAMPLE_RATE, ResampleQuality::Fast),
+            pending: Vec::new(),
+            processed: 0,
+            in_speech: false,
+            speech_frames: 0,
+            silence_frames: 0,
+            speech_start: 0,
+            gaps: 0.0,
+        }
+    }
+
+    // Feed mono device-rate samples from the recording thread
+    pub fn process(&mut self, data: &[f32]) -> Vec<VadEvent> {
+        self.pending.extend(self.resampler.process(data));
+
+        let mut events = Vec::new();
+        let frame_len = self.classifier.frame_len();
+
+        while self.pending.len() >= frame_len {
+            let frame: Vec<f32> = self.pending.drain(..frame_len).collect();
+            let is_speech = self.classifier.speech_probability(&frame) > SPEECH_PROBABILITY;
+            self.processed += frame_len;
+
+            if let Some(event) = self.update(is_speech, frame_len) {
+                events.push(event);
+            }
+        }
+
+        events
+    }
+
+    // Advance the speech state machine by one frame
+    fn update(&mut self, is_speech: bool, frame_len: usize) -> Option<VadEvent> {
+        let frames = |ms: usize| (ms * VAD_SAMPLE_RATE as usize / 1000).div_ceil(frame_len);
+
+        if is_speech {
+            self.speech_frames += 1;
+            self.silence_frames = 0;
+        } else {
+            self.silence_frames += 1;
+            if !self.in_speech {
+                self.speech_frames = 0;
+            }
+        }
+
+        if !self.in_speech && self.speech_frames >= frames(SPEECH_START_MS) {
+            // Backdate the start to the first speech frame
+            self.in_speech = true;
+            self.speech_start = self.processed - self.speech_frames * frame_len;
+
+            return Some(VadEvent::SpeechStart {
+                time: self.seconds(self.speech_start),
+            });
+        }
+
+        if self.in_speech && self.silence_frames >= frames(SPEECH_END_MS) {
+            self.in_speech = false;
+            self.speech_frames = 0;
+            let speech_end = self.processed - self.silence_frames * frame_len;
+
+            // The segment runs to the current position so the trailing silence
+            // isn't lost between segments
+            return Some(VadEvent::SpeechEnd {
+                offset: self.raw_offset(self.processed),
+                start: self.seconds(self.speech_start),
+                end: self.seconds(speech_end),
+            });
+        }
+
+        None
+    }
+
+    // End the current utterance at the last classified frame, when the capture is
+    // paused. The detector carries on from silence once audio arrives again.
+    pub fn interrupt(&mut self) -> Option<VadEvent> {
+        let was_in_speech = self.in_speech;
+        self.in_speech = false;
+        self.speech_frames = 0;
+        self.silence_frames = 0;
+
+        was_in_speech.then(|| VadEvent::SpeechEnd {
+            offset: self.raw_offset(self.processed),
+            start: self.seconds(self.speech_start),
+            end: self.seconds(self.processed),
+        })
+    }
+
+    // Account for a pause of `seconds` in the times of later events. Called once the
+    // capture resumes, after `interrupt`.
+    pub fn skip_gap(&mut self, seconds: f32) {
+        self.gaps += seconds;
+    }
+
+    // Offset into the capture buffer for a 16kHz sample position
+    fn raw_offset(&self, position: usize) -> usize {
+        (position as f64 * self.step) as usize
+    }
+
+    // Session time of a 16kHz sample position
+    fn seconds(&self, position: usize) -> f32 {
+        position as f32 / VAD_SAMPLE_RATE as f32 + self.gaps
+    }
+}
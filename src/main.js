@@ -1,15 +1,27 @@
 const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
 
 // UI Elements
 let toggleRecordingBtn;
 let toggleRecordingText;
+let togglePauseBtn;
 let recordingIndicator;
 let transcriptEl;
 let statusMessageEl;
 let audioDeviceSelect;
+let audioChannelSelect;
+let deviceLossSelect;
+let outputMonitorGroup;
+let outputMonitorSelect;
+let loopbackRulesInput;
+let loopbackMatchEl;
 let micSourceBtn;
 let systemSourceBtn;
+let bothSourceBtn;
 let diarizationToggle;
+let saveRecordingsToggle;
+let recordingFormatSelect;
+let levelMeterEl;
 let speakerRenameModal;
 let speakerRenameForm;
 let speakerRenameInput;
@@ -18,13 +30,17 @@ let speakerRenameCancel;
 
 // State
 let isRecording = false;
-let silenceTimer = null;
-let silenceTimeout = 2000; // 2 seconds of silence before transcribing
-let lastAudioLevel = 0;
-let audioLevelCheckInterval = null;
-let isSystemAudio = false; // Track if we're using system audio
+let audioSource = 'microphone'; // 'microphone', 'system' or 'both'
 let useDiarization = false; // Track if diarization is enabled
 let currentSpeakers = new Map(); // Map to store speaker names
+let isStreaming = false; // Track if live captions are being streamed
+let isPaused = false; // Recording is paused; the sessions stay open
+let captureSessions = []; // Backend capture sessions while recording; two when capturing both sides
+let partialEntries = new Map(); // Capture session id -> transcript entry showing its in-progress caption
+let sessionLevels = new Map(); // Capture session id -> latest input levels
+let pendingJobs = new Map(); // Transcription job id -> whether diarization was requested
+let deadInputWarned = false; // Whether the user has been told the input has no signal
+let hasOutputMonitors = false; // Whether outputs can be captured directly, without a virtual device
 
 // Initialize the application
 window.addEventListener("DOMContentLoaded", () => {
@@ -32,28 +48,214 @@ window.addEventListener("DOMContentLoaded", () => {
   toggleRecordingBtn = document.querySelector("#toggle-recording");
   toggleRecordingText = toggleRecordingBtn.querySelector(".button-text");
   recordingIndicator = toggleRecordingBtn.querySelector(".recording-indicator");
+  togglePauseBtn = document.querySelector("#toggle-pause");
   transcriptEl = document.querySelector("#transcript");
   statusMessageEl = document.querySelector("#status-message");
   audioDeviceSelect = document.querySelector("#audio-device");
+  audioChannelSelect = document.querySelector("#audio-channel");
+  deviceLossSelect = document.querySelector("#device-loss");
+  outputMonitorGroup = document.querySelector("#output-monitor-group");
+  outputMonitorSelect = document.querySelector("#output-monitor");
+  loopbackRulesInput = document.querySelector("#loopback-rules");
+  loopbackMatchEl = document.querySelector("#loopback-match");
   micSourceBtn = document.querySelector("#mic-source");
   systemSourceBtn = document.querySelector("#system-source");
+  bothSourceBtn = document.querySelector("#both-source");
   diarizationToggle = document.querySelector("#diarization-toggle");
+  saveRecordingsToggle = document.querySelector("#save-recordings");
+  recordingFormatSelect = document.querySelector("#recording-format");
+  levelMeterEl = document.querySelector("#level-meter");
   
-  // Create speaker rename modal elements
+  // Recordings left on disk by a session that never stopped, e.g. after a crash
+async function offerRecoverableRecordings() {
+  let recordings;
+  try {
+    recordings = await invoke("list_recoverable_recordings");
+  } catch (error) {
+    console.error("Failed to list recoverable recordings:", error);
+    return;
+  }
+  
+  recordings.forEach(recording => {
+    const started = new Date(recording.started).toLocaleString();
+    const minutes = Math.max(1, Math.round(recording.duration_secs / 60));
+    const entry = appendTranscript(`A recording from ${started} (${minutes} min) was not finished. Transcribe it?`, true, recording.source);
+    
+    const buttons = document.createElement("div");
+    buttons.className = "button-container";
+    const transcribeBtn = document.createElement("button");
+    transcribeBtn.className = "secondary-button";
+    transcribeBtn.textContent = "Transcribe";
+    const discardBtn = document.createElement("button");
+    discardBtn.className = "secondary-button";
+    discardBtn.textContent = "Discard";
+    buttons.append(discardBtn, transcribeBtn);
+    entry.querySelector(".content").appendChild(buttons);
+    
+    transcribeBtn.addEventListener("click", async () => {
+      try {
+        const diarize = useDiarization && recording.source !== "me";
+        await invoke("recover_recording", { recordingId: recording.id, diarize });
+        buttons.remove();
+      } catch (error) {
+        showErrorMessage(`Failed to recover recording: ${error}`);
+      }
+    });
+    discardBtn.addEventListener("click", async () => {
+      try {
+        await invoke("discard_recording", { recordingId: recording.id });
+        entry.remove();
+      } catch (error) {
+        showErrorMessage(`Failed to discard recording: ${error}`);
+      }
+    });
+  });
+}
+
+// Create speaker rename modal elements
   createSpeakerRenameModal();
   
   // Set up event listeners
   toggleRecordingBtn.addEventListener("click", toggleRecording);
+  togglePauseBtn.addEventListener("click", togglePause);
   audioDeviceSelect.addEventListener("change", handleDeviceChange);
+  audioChannelSelect.addEventListener("change", handleChannelChange);
+  deviceLossSelect.addEventListener("change", handleDeviceLossChange);
+  outputMonitorSelect.addEventListener("change", handleOutputMonitorChange);
+  document.querySelector("#save-loopback-rules").addEventListener("click", saveLoopbackRules);
+  document.querySelector("#reset-loopback-rules").addEventListener("click", resetLoopbackRules);
+  document.querySelector("#check-loopback-rules").addEventListener("click", checkSystemDevice);
   micSourceBtn.addEventListener("change", () => setAudioSource('microphone'));
   systemSourceBtn.addEventListener("change", () => setAudioSource('system'));
+  bothSourceBtn.addEventListener("change", () => setAudioSource('both'));
   diarizationToggle.addEventListener("change", toggleDiarization);
+  saveRecordingsToggle.addEventListener("change", handleRecordingOptionsChange);
+  recordingFormatSelect.addEventListener("change", handleRecordingOptionsChange);
+  
+  // Escape cancels any transcriptions that are still pending
+  document.addEventListener("keydown", (event) => {
+    if (event.key === "Escape" && speakerRenameModal.style.display === 'none') {
+      pendingJobs.forEach((_, jobId) => cancelTranscription(jobId));
+    }
+  });
+  
+  // Listen for live captions from the streaming transcriber
+  listen("caption-partial", (event) => {
+    const { session_id, text, source } = event.payload;
+    updatePartialCaption(session_id, text, source);
+  });
+  listen("caption-final", (event) => {
+    const { session_id, segment, recording } = event.payload;
+    updatePartialCaption(session_id, "");
+    if (segment.text) {
+      const entry = appendTranscript(segment.text, false, segment.source);
+      addRecordingControls(entry, recording, segment.start, segment.end);
+    }
+  });
+  listen("caption-error", (event) => showErrorMessage(`Live captioning error: ${event.payload.message}`));
+  
+  // Input levels from the capture thread
+  listen("audio-level", (event) => updateLevelMeter(event.payload));
+  
+  // Inputs being plugged in or removed
+  listen("input-devices-changed", (event) => {
+    const { added, removed, devices } = event.payload;
+    const selected = audioDeviceSelect.value;
+    updateAudioDevices(devices);
+    
+    if (removed.includes(selected) && !isRecording) {
+      showErrorMessage("The selected input device was disconnected");
+    } else if (added.length > 0) {
+      showStatusMessage(`Input connected: ${added.map(device => device.name).join(", ")}`);
+      setTimeout(clearStatusMessage, 3000);
+    }
+  });
+  
+  // Errors reported by a capture stream. Fatal ones also end in capture-device-lost,
+  // which decides whether the recording carries on.
+  listen("capture-stream-error", (event) => {
+    const { session_id, device, kind, message, fatal } = event.payload;
+    if (!captureSessions.some(session => session.id === session_id)) {
+      return;
+    }
+    
+    console.error(`Stream error (${kind}) on ${device}: ${message}`);
+    if (!fatal) {
+      showErrorMessage(`Audio error on ${device}: ${message}`);
+    }
+  });
+  
+  // The device of a running capture went away
+  listen("capture-device-lost", async (event) => {
+    const { session_id, device, message, reconnecting } = event.payload;
+    if (!captureSessions.some(session => session.id === session_id)) {
+      return;
+    }
+    
+    if (reconnecting) {
+      showErrorMessage(`${device} disconnected, waiting for it to come back...`);
+    } else {
+      showErrorMessage(`${device} disconnected: ${message}. Recording stopped.`);
+      if (isRecording) {
+        await stopRecording();
+      }
+    }
+  });
+  listen("capture-device-reconnected", (event) => {
+    showStatusMessage(`${event.payload.device} reconnected, recording resumed`);
+    setTimeout(clearStatusMessage, 3000);
+  });
+  
+  // Voice activity from the backend
+  listen("speech-start", () => recordingIndicator.classList.add("speaking"));
+  listen("speech-end", () => recordingIndicator.classList.remove("speaking"));
+  
+  // Listen for background transcription jobs, including utterances committed by the backend
+  listen("transcription-queued", (event) => pendingJobs.set(event.payload.job_id, event.payload.diarize));
+  listen("transcription-progress", (event) => {
+    if (pendingJobs.has(event.payload.job_id)) {
+      showStatusMessage(`Transcribing... ${event.payload.progress}%`);
+    }
+  });
+  listen("transcription-complete", (event) => {
+    const { job_id, transcript, recording } = event.payload;
+    const diarized = pendingJobs.get(job_id);
+    pendingJobs.delete(job_id);
+    clearStatusMessage();
+    displayTranscript(transcript, diarized, job_id, recording);
+  });
+  listen("transcription-failed", (event) => {
+    pendingJobs.delete(event.payload.job_id);
+    showErrorMessage(`Transcription failed: ${event.payload.error}`);
+  });
+  listen("transcription-cancelled", (event) => {
+    pendingJobs.delete(event.payload.job_id);
+    showStatusMessage("Transcription cancelled");
+    setTimeout(clearStatusMessage, 3000);
+  });
+  
+  // Speakers are re-clustered across the whole session once recording stops
+  listen("diarization-session-complete", (event) => relabelSpeakers(event.payload));
+  
+  // Session audio written to disk after a recording stops
+  listen("recording-saved", (event) => {
+    showStatusMessage(`Recording saved (${formatDuration(event.payload.duration_secs)})`);
+    setTimeout(clearStatusMessage, 3000);
+  });
+  listen("recording-failed", (event) => showErrorMessage(`Failed to save the recording: ${event.payload.error}`));
   
   // Populate audio devices
   populateAudioDevices();
+  loadDeviceLossPolicy();
+  loadRecordingOptions();
+  populateOutputMonitors();
+  loadLoopbackRules();
   
   // Show welcome message
   appendTranscript("Welcome to Disrust Captioner! Click 'Start Recording' to begin capturing audio.", true);
+  
+  // Offer recordings that were cut short last time
+  offerRecoverableRecordings();
 });
 
 // Create speaker rename modal
@@ -154,33 +356,37 @@ async function toggleDiarization() {
   setTimeout(clearStatusMessage, 3000);
 }
 
-// Set audio source (microphone or system)
+// Set audio source (microphone, system or both)
 async function setAudioSource(source) {
   if (isRecording) {
     // Stop recording before changing source
     await stopRecording();
   }
   
-  isSystemAudio = source === 'system';
+  audioSource = source;
   
   // Update UI
-  if (isSystemAudio) {
-    micSourceBtn.checked = false;
-    systemSourceBtn.checked = true;
-    document.querySelector('label[for="mic-source"]').classList.remove('active');
-    document.querySelector('label[for="system-source"]').classList.add('active');
+  const buttons = { microphone: micSourceBtn, system: systemSourceBtn, both: bothSourceBtn };
+  Object.entries(buttons).forEach(([name, button]) => {
+    button.checked = name === source;
+    document.querySelector(`label[for="${button.id}"]`).classList.toggle('active', name === source);
+  });
+  
+  outputMonitorGroup.hidden = !hasOutputMonitors || source === 'microphone';
+  
+  if (source === 'system' && hasOutputMonitors) {
+    showStatusMessage("System audio mode activated. Capturing the selected output directly.");
+  } else if (source === 'system') {
     showStatusMessage("System audio mode activated. Make sure you have the proper virtual audio device set up.");
+  } else if (source === 'both') {
+    showStatusMessage("Capturing your microphone and system audio. Captions are labelled by side of the call.");
   } else {
-    systemSourceBtn.checked = false;
-    micSourceBtn.checked = true;
-    document.querySelector('label[for="system-source"]').classList.remove('active');
-    document.querySelector('label[for="mic-source"]').classList.add('active');
     showStatusMessage("Microphone mode activated.");
   }
   
-  // Tell the backend which source to use
+  // Tell the backend which source to use; both sides are opened explicitly when capturing both
   try {
-    await invoke("set_audio_source", { isSystem: isSystemAudio });
+    await invoke("set_audio_source", { isSystem: source === 'system' });
   } catch (error) {
     console.error("Failed to set audio source:", error);
     showErrorMessage(`Failed to set audio source: ${error}`);
@@ -205,6 +411,7 @@ async function populateAudioDevices() {
       const option = document.createElement("option");
       option.value = device.id;
       option.textContent = device.name;
+      option.dataset.channels = device.channels;
       audioDeviceSelect.appendChild(option);
     });
     
@@ -212,12 +419,223 @@ async function populateAudioDevices() {
     if (devices.length > 0) {
       audioDeviceSelect.value = devices[0].id;
     }
+    populateChannels();
   } catch (error) {
     console.error("Failed to get input devices:", error);
     showErrorMessage(`Failed to get input devices: ${error}`);
   }
 }
 
+// List the outputs whose audio can be captured without a virtual device (Linux only)
+async function populateOutputMonitors() {
+  try {
+    const monitors = await invoke("list_output_monitors");
+    
+    monitors.forEach(monitor => {
+      const option = document.createElement("option");
+      option.value = monitor.name;
+      option.textContent = monitor.is_default ? `${monitor.description} (current)` : monitor.description;
+      outputMonitorSelect.appendChild(option);
+    });
+    
+    hasOutputMonitors = monitors.length > 0;
+  } catch (error) {
+    // No sound server to ask; system audio falls back to a virtual device
+    console.log("Output monitors unavailable:", error);
+    hasOutputMonitors = false;
+  }
+  outputMonitorGroup.hidden = !hasOutputMonitors || audioSource === 'microphone';
+}
+
+// Handle output selection change; takes effect from the next recording
+async function handleOutputMonitorChange() {
+  const monitorName = outputMonitorSelect.value;
+  
+  try {
+    if (isRecording) {
+      await stopRecording();
+    }
+    
+    await invoke("set_output_monitor", { monitorName: monitorName === "default" ? null : monitorName });
+    
+    showStatusMessage(`Capturing output: ${outputMonitorSelect.options[outputMonitorSelect.selectedIndex].text}`);
+    setTimeout(clearStatusMessage, 3000);
+  } catch (error) {
+    console.error("Failed to change output:", error);
+    showErrorMessage(`Failed to change output: ${error}`);
+  }
+}
+
+// Show the loopback device rules, one "kind: pattern" per line
+function showLoopbackRules(rules) {
+  loopbackRulesInput.value = rules.map(rule => `${rule.kind}: ${rule.pattern}`).join("\n");
+}
+
+async function loadLoopbackRules() {
+  try {
+    showLoopbackRules(await invoke("get_loopback_rules"));
+  } catch (error) {
+    console.error("Failed to load virtual device rules:", error);
+  }
+}
+
+// Parse the rules editor; lines without a kind are substring rules
+function parseLoopbackRules() {
+  return loopbackRulesInput.value
+    .split("\n")
+    .map(line => line.trim())
+    .filter(line => line.length > 0)
+    .map(line => {
+      const match = line.match(/^(substring|exact|regex):\s*(.*)$/i);
+      return match
+        ? { kind: match[1].toLowerCase(), pattern: match[2] }
+        : { kind: "substring", pattern: line };
+    });
+}
+
+async function saveLoopbackRules() {
+  try {
+    await invoke("set_loopback_rules", { rules: parseLoopbackRules() });
+    showStatusMessage("Virtual device rules saved");
+    setTimeout(clearStatusMessage, 3000);
+    await checkSystemDevice();
+  } catch (error) {
+    showErrorMessage(`Failed to save virtual device rules: ${error}`);
+  }
+}
+
+async function resetLoopbackRules() {
+  try {
+    showLoopbackRules(await invoke("reset_loopback_rules"));
+    await checkSystemDevice();
+  } catch (error) {
+    showErrorMessage(`Failed to reset virtual device rules: ${error}`);
+  }
+}
+
+// Report which device system audio would come from, and which rule picked it
+async function checkSystemDevice() {
+  try {
+    const match = await invoke("match_system_device");
+    loopbackMatchEl.textContent = `${match.device}: ${match.reason}`;
+  } catch (error) {
+    loopbackMatchEl.textContent = `${error}`;
+  }
+}
+
+// Refresh the device list after a hot-plug change, keeping the selection if it is still there
+function updateAudioDevices(devices) {
+  const selected = audioDeviceSelect.value;
+  const selectedName = audioDeviceSelect.options[audioDeviceSelect.selectedIndex]?.textContent.replace(/ \(disconnected\)$/, "");
+  
+  while (audioDeviceSelect.options.length > 1) {
+    audioDeviceSelect.remove(1);
+  }
+  devices.forEach(device => {
+    const option = document.createElement("option");
+    option.value = device.id;
+    option.textContent = device.name;
+    option.dataset.channels = device.channels;
+    audioDeviceSelect.appendChild(option);
+  });
+  
+  if (selected === "default" || devices.some(device => device.id === selected)) {
+    audioDeviceSelect.value = selected;
+  } else {
+    // Leave the missing device selected so recording fails instead of using another input
+    const option = document.createElement("option");
+    option.value = selected;
+    option.textContent = `${selectedName} (disconnected)`;
+    audioDeviceSelect.appendChild(option);
+    audioDeviceSelect.value = selected;
+  }
+}
+
+async function loadDeviceLossPolicy() {
+  try {
+    deviceLossSelect.value = await invoke("get_device_loss_policy");
+  } catch (error) {
+    console.error("Failed to load the disconnect setting:", error);
+  }
+}
+
+async function handleDeviceLossChange() {
+  try {
+    await invoke("set_device_loss_policy", { policy: deviceLossSelect.value });
+  } catch (error) {
+    showErrorMessage(`Failed to save the disconnect setting: ${error}`);
+  }
+}
+
+// Ogg Opus is only offered when the backend was built with it
+async function loadRecordingOptions() {
+  try {
+    const formats = await invoke("list_recording_formats");
+    if (formats.includes("opus")) {
+      const option = document.createElement("option");
+      option.value = "opus";
+      option.textContent = "Ogg Opus";
+      recordingFormatSelect.appendChild(option);
+    }
+    
+    const options = await invoke("get_recording_options");
+    saveRecordingsToggle.checked = options.enabled;
+    recordingFormatSelect.value = options.format;
+  } catch (error) {
+    console.error("Failed to load the recording settings:", error);
+  }
+}
+
+// Takes effect from the next recording
+async function handleRecordingOptionsChange() {
+  try {
+    await invoke("set_recording_options", {
+      options: { enabled: saveRecordingsToggle.checked, format: recordingFormatSelect.value },
+    });
+  } catch (error) {
+    showErrorMessage(`Failed to save the recording settings: ${error}`);
+  }
+}
+
+// Offer each channel of the selected device, plus the mix of all of them
+function populateChannels() {
+  const selected = audioDeviceSelect.options[audioDeviceSelect.selectedIndex];
+  const channels = Number(selected?.dataset.channels || 1);
+  
+  while (audioChannelSelect.options.length > 1) {
+    audioChannelSelect.remove(1);
+  }
+  
+  if (channels > 1) {
+    for (let index = 0; index < channels; index++) {
+      const option = document.createElement("option");
+      option.value = index;
+      option.textContent = `Channel ${index + 1}`;
+      audioChannelSelect.appendChild(option);
+    }
+  }
+  
+  audioChannelSelect.value = "downmix";
+  audioChannelSelect.disabled = channels <= 1;
+  handleChannelChange();
+}
+
+// Handle channel selection change; takes effect from the next recording
+async function handleChannelChange() {
+  const value = audioChannelSelect.value;
+  const selection = value === "downmix"
+    ? { mode: "downmix" }
+    : { mode: "channel", index: Number(value) };
+  
+  try {
+    const options = await invoke("get_channel_options");
+    await invoke("set_channel_options", { options: { ...options, selection } });
+  } catch (error) {
+    console.error("Failed to set channel:", error);
+    showErrorMessage(`Failed to set channel: ${error}`);
+  }
+}
+
 // Toggle recording state
 async function toggleRecording() {
   if (isRecording) {
@@ -230,28 +648,102 @@ async function toggleRecording() {
 // Start recording
 async function startRecording() {
   try {
-    // Start recording based on selected source
-    if (isSystemAudio) {
-      await invoke("start_recording_system");
+    // The last recording's sessions are kept until a new one starts, so their audio stays available
+    for (const session of captureSessions) {
+      await invoke("close_capture", { sessionId: session.id }).catch(error => console.error("Failed to close capture session:", error));
+    }
+    captureSessions = [];
+    
+    // Start recording based on selected source; the backend reports the format it negotiated
+    if (audioSource === 'both') {
+      captureSessions = await invoke("start_dual_recording");
+    } else if (audioSource === 'system') {
+      captureSessions = [await invoke("start_recording_system")];
     } else {
-      await invoke("start_recording");
+      captureSessions = [await invoke("start_recording")];
     }
     
     // Update UI
     isRecording = true;
     toggleRecordingText.textContent = "Stop Recording";
     recordingIndicator.classList.add("active");
+    togglePauseBtn.hidden = false;
     
-    // Start silence detection
-    startSilenceDetection();
+    // Each side of a call is transcribed on its own
+    for (const session of captureSessions) {
+      if (useDiarization) {
+        // Diarization needs whole utterances, so the backend transcribes each one when speech ends.
+        // Your own microphone only has one speaker.
+        await invoke("start_segmenting", { sessionId: session.id, diarize: session.source !== "me" });
+      } else {
+        // Stream live captions while the capture keeps running
+        await invoke("start_streaming", { sessionId: session.id });
+        isStreaming = true;
+      }
+    }
     
-    showStatusMessage("Recording started...");
+    const formats = captureSessions.map(session => describeCaptureFormat(session.format));
+    showStatusMessage(`Recording started... (${formats.join("; ")})`);
   } catch (error) {
     console.error("Failed to start recording:", error);
     showErrorMessage(`Failed to start recording: ${error}`);
   }
 }
 
+// Pause or resume every session of the recording
+async function togglePause() {
+  const sessionIds = captureSessions.map(session => session.id);
+  
+  try {
+    if (isPaused) {
+      const sessions = await invoke("resume_recording", { sessionIds });
+      isPaused = false;
+      togglePauseBtn.textContent = "Pause";
+      recordingIndicator.classList.remove("paused");
+      
+      // Mark the gap where it falls in the transcript
+      const pause = sessions[0]?.pauses.at(-1);
+      if (pause?.duration != null) {
+        const entry = appendTranscript(`Paused for ${formatDuration(pause.duration)}`, true);
+        entry.classList.add("pause-marker");
+      }
+      showStatusMessage("Recording resumed");
+      setTimeout(clearStatusMessage, 3000);
+    } else {
+      await invoke("pause_recording", { sessionIds });
+      isPaused = true;
+      togglePauseBtn.textContent = "Resume";
+      recordingIndicator.classList.add("paused");
+      recordingIndicator.classList.remove("speaking");
+      showStatusMessage("Recording paused. Nothing is captured until you resume.");
+    }
+  } catch (error) {
+    console.error("Failed to pause or resume recording:", error);
+    showErrorMessage(`Failed to ${isPaused ? "resume" : "pause"} recording: ${error}`);
+  }
+}
+
+// Hide the pause button once the recording has stopped
+function resetPause() {
+  isPaused = false;
+  togglePauseBtn.hidden = true;
+  togglePauseBtn.textContent = "Pause";
+  recordingIndicator.classList.remove("paused");
+}
+
+// e.g. 75 -> "1m 15s"
+function formatDuration(seconds) {
+  const minutes = Math.floor(seconds / 60);
+  const rest = Math.round(seconds % 60);
+  return minutes > 0 ? `${minutes}m ${rest}s` : `${rest}s`;
+}
+
+// e.g. "48 kHz, 2 channels, i16"
+function describeCaptureFormat(format) {
+  const channels = format.channels === 1 ? "mono" : `${format.channels} channels`;
+  return `${format.sample_rate / 1000} kHz, ${channels}, ${format.sample_format}`;
+}
+
 // Handle device change
 async function handleDeviceChange() {
   const deviceId = audioDeviceSelect.value;
@@ -264,6 +756,7 @@ async function handleDeviceChange() {
     
     // Set the selected device
     await invoke("set_input_device", { deviceId: deviceId === "default" ? null : deviceId });
+    populateChannels();
     
     showStatusMessage(`Audio device changed to: ${audioDeviceSelect.options[audioDeviceSelect.selectedIndex].text}`);
     
@@ -278,22 +771,45 @@ async function handleDeviceChange() {
 // Stop recording
 async function stopRecording(updateUI = true) {
   try {
-    // Stop silence detection
-    stopSilenceDetection();
-    
-    // Get transcription based on selected source and diarization setting
-    let transcript;
-    if (isSystemAudio) {
-      if (useDiarization) {
-        transcript = await invoke("stop_recording_system_with_diarization");
-      } else {
-        transcript = await invoke("stop_recording_system");
+    // Live captions have already been appended as they were finalized
+    if (isStreaming) {
+      for (const session of captureSessions) {
+        await invoke("stop_streaming", { sessionId: session.id });
       }
-    } else {
-      if (useDiarization) {
-        transcript = await invoke("stop_recording_with_diarization");
+      isStreaming = false;
+      
+      if (updateUI) {
+        isRecording = false;
+        toggleRecordingText.textContent = "Start Recording";
+        recordingIndicator.classList.remove("active");
+        resetPause();
+        resetLevelMeter();
+      }
+      
+      return null;
+    }
+    
+    // Queue transcription based on selected source and diarization setting.
+    // The result arrives later as a transcription-complete event.
+    // Your own side is stopped first so the diarized side ends the diarization session.
+    let jobId;
+    for (const session of captureSessions) {
+      const args = { sessionId: session.id };
+      const system = audioSource === 'system' || session.source === "remote";
+      const diarize = useDiarization && session.source !== "me";
+      
+      if (system) {
+        if (diarize) {
+          jobId = await invoke("stop_recording_system_with_diarization", args);
+        } else {
+          jobId = await invoke("stop_recording_system", args);
+        }
       } else {
-        transcript = await invoke("stop_recording");
+        if (diarize) {
+          jobId = await invoke("stop_recording_with_diarization", args);
+        } else {
+          jobId = await invoke("stop_recording", args);
+        }
       }
     }
     
@@ -302,18 +818,11 @@ async function stopRecording(updateUI = true) {
       isRecording = false;
       toggleRecordingText.textContent = "Start Recording";
       recordingIndicator.classList.remove("active");
+      resetPause();
+      resetLevelMeter();
     }
     
-    // Process and display transcript
-    if (transcript) {
-      if (useDiarization) {
-        appendDiarizedTranscript(transcript);
-      } else {
-        appendTranscript(transcript);
-      }
-    }
-    
-    return transcript;
+    return jobId;
   } catch (error) {
     console.error("Failed to stop recording:", error);
     showErrorMessage(`Failed to stop recording: ${error}`);
@@ -323,79 +832,151 @@ async function stopRecording(updateUI = true) {
       isRecording = false;
       toggleRecordingText.textContent = "Start Recording";
       recordingIndicator.classList.remove("active");
+      resetPause();
+      resetLevelMeter();
     }
     
     return null;
   }
 }
 
-// Start silence detection
-function startSilenceDetection() {
-  // Clear any existing interval
-  if (audioLevelCheckInterval) {
-    clearInterval(audioLevelCheckInterval);
-  }
-  
-  // Set up interval to check audio level
-  audioLevelCheckInterval = setInterval(() => {
-    // This is a placeholder for actual audio level detection
-    // In a real implementation, you would get this from the audio stream
-    const currentAudioLevel = Math.random(); // Simulate random audio level
-    
-    // Check if audio level is below threshold (silence)
-    if (currentAudioLevel < 0.1) {
-      // If we were previously not in silence, start the timer
-      if (lastAudioLevel >= 0.1 && !silenceTimer) {
-        console.log("Silence detected, starting timer...");
-        silenceTimer = setTimeout(async () => {
-          // If we're still recording, transcribe the current audio
-          if (isRecording) {
-            console.log("Silence timeout reached, processing audio...");
-            // Stop recording temporarily without updating UI
-            const transcript = await stopRecording(false);
-            
-            // If we got a transcript, restart recording
-            if (transcript) {
-              console.log("Transcript received, restarting recording");
-              await startRecording();
-            }
-          }
-          
-          // Clear the timer
-          silenceTimer = null;
-        }, silenceTimeout);
-      }
+// Cancel a queued or running transcription job
+async function cancelTranscription(jobId) {
+  try {
+    await invoke("cancel_transcription", { jobId });
+  } catch (error) {
+    console.error("Failed to cancel transcription:", error);
+  }
+}
+
+// Draw the input level meter and warn about inputs with no signal
+function updateLevelMeter(levels) {
+  if (!isRecording) return;
+  
+  // Audio was dropped since the last update because processing fell behind
+  const previous = sessionLevels.get(levels.session_id);
+  if (levels.overruns > (previous?.overruns ?? 0)) {
+    console.warn(`Capture overrun: ${levels.dropped_frames} frames dropped in ${levels.overruns} overruns`);
+    showErrorMessage("Audio processing fell behind and some audio was dropped. Close other busy apps if this keeps happening.");
+  }
+  
+  // One bar per channel of every capture session
+  sessionLevels.set(levels.session_id, levels);
+  const channels = [...sessionLevels.values()].flatMap(session => session.channels);
+  
+  while (levelMeterEl.children.length !== channels.length) {
+    if (levelMeterEl.children.length < channels.length) {
+      const bar = document.createElement("div");
+      bar.className = "level-bar";
+      const fill = document.createElement("div");
+      fill.className = "level-fill";
+      bar.appendChild(fill);
+      levelMeterEl.appendChild(bar);
     } else {
-      // If we're not in silence, clear any existing timer
-      if (silenceTimer) {
-        console.log("Speech detected, clearing silence timer");
-        clearTimeout(silenceTimer);
-        silenceTimer = null;
-      }
+      levelMeterEl.lastChild.remove();
     }
+  }
+  
+  // Show RMS on a -60..0 dBFS scale
+  channels.forEach((level, index) => {
+    const percent = Math.max(0, Math.min(100, (level.rms_db + 60) / 60 * 100));
+    levelMeterEl.children[index].firstChild.style.width = `${percent}%`;
+  });
+  levelMeterEl.classList.toggle("clipping", [...sessionLevels.values()].some(session => session.clipping));
+  
+  // The call side is silent whenever nobody else is talking, so only warn about the microphone
+  const session = captureSessions.find(session => session.id === levels.session_id);
+  if (session?.source === "remote") return;
+  
+  if (levels.dead_input && !deadInputWarned) {
+    deadInputWarned = true;
+    showErrorMessage("No signal from the selected audio device. Check that it is connected and not muted.");
+  } else if (!levels.dead_input && deadInputWarned) {
+    deadInputWarned = false;
+    clearStatusMessage();
+  }
+}
+
+// Clear the level meter when recording stops
+function resetLevelMeter() {
+  sessionLevels.clear();
+  levelMeterEl.replaceChildren();
+  levelMeterEl.classList.remove("clipping");
+  deadInputWarned = false;
+}
+
+// Display a finished transcript. `recording` places it in saved session audio, if any.
+function displayTranscript(transcript, diarized, jobId, recording = null) {
+  if (transcript.notice) {
+    // Diarized sessions are split into utterances, so an empty one isn't worth showing
+    if (diarized) {
+      console.log(`Job ${jobId}: ${transcript.notice}`);
+    } else {
+      appendTranscript(transcript.notice);
+    }
+  } else if (diarized) {
+    appendDiarizedTranscript(transcript, jobId, recording);
+  } else {
+    const source = transcript.segments[0]?.source;
+    const entry = appendTranscript(transcript.segments.map(segment => segment.text).join(" "), false, source);
     
-    // Update last audio level
-    lastAudioLevel = currentAudioLevel;
-  }, 100); // Check every 100ms
+    const segments = transcript.segments;
+    if (segments.length > 0) {
+      addRecordingControls(entry, recording, segments[0].start, segments[segments.length - 1].end);
+    }
+  }
 }
 
-// Stop silence detection
-function stopSilenceDetection() {
-  // Clear interval
-  if (audioLevelCheckInterval) {
-    clearInterval(audioLevelCheckInterval);
-    audioLevelCheckInterval = null;
+// Replay and re-transcribe buttons for an entry whose audio was saved.
+// `start` and `end` are the entry's times in the transcript that `recording` links.
+function addRecordingControls(entry, recording, start, end) {
+  if (!recording) {
+    return;
   }
   
-  // Clear timer
-  if (silenceTimer) {
-    clearTimeout(silenceTimer);
-    silenceTimer = null;
-  }
+  const recordingId = recording.recording_id;
+  start += recording.offset;
+  end += recording.offset;
+  
+  const controls = document.createElement("div");
+  controls.className = "recording-controls";
+  
+  const playBtn = document.createElement("button");
+  playBtn.className = "recording-button";
+  playBtn.textContent = "▶";
+  playBtn.title = "Play this part of the recording";
+  playBtn.addEventListener("click", async () => {
+    try {
+      const wav = await invoke("get_recording_clip", { recordingId, start, end });
+      const url = URL.createObjectURL(new Blob([wav], { type: "audio/wav" }));
+      const audio = new Audio(url);
+      audio.addEventListener("ended", () => URL.revokeObjectURL(url));
+      await audio.play();
+    } catch (error) {
+      showErrorMessage(`Failed to play the recording: ${error}`);
+    }
+  });
+  
+  const retranscribeBtn = document.createElement("button");
+  retranscribeBtn.className = "recording-button";
+  retranscribeBtn.textContent = "↻";
+  retranscribeBtn.title = "Transcribe this part of the recording again";
+  retranscribeBtn.addEventListener("click", async () => {
+    try {
+      await invoke("retranscribe_recording", { recordingId, start, end, diarize: useDiarization });
+      showStatusMessage("Transcribing again...");
+    } catch (error) {
+      showErrorMessage(`Failed to transcribe the recording again: ${error}`);
+    }
+  });
+  
+  controls.appendChild(playBtn);
+  controls.appendChild(retranscribeBtn);
+  entry.appendChild(controls);
 }
 
-// Append transcript to the UI
-function appendTranscript(text, isSystem = false) {
+// Append transcript to the UI. `source` labels which side of a call the text came from.
+function appendTranscript(text, isSystem = false, source = null) {
   // Create a new transcript entry
   const entry = document.createElement("div");
   entry.className = "transcript-entry";
@@ -418,6 +999,9 @@ function appendTranscript(text, isSystem = false) {
   
   // Assemble entry
   entry.appendChild(timestamp);
+  if (source) {
+    entry.appendChild(createSourceLabel(source));
+  }
   entry.appendChild(content);
   
   // Add to transcript
@@ -425,87 +1009,158 @@ function appendTranscript(text, isSystem = false) {
   
   // Scroll to bottom
   transcriptEl.scrollTop = transcriptEl.scrollHeight;
+  
+  return entry;
 }
 
-// Append diarized transcript to the UI
-function appendDiarizedTranscript(text) {
-  // Split the text into lines
-  const lines = text.split('\n');
+// Show a capture session's in-progress caption below the finalized transcript
+function updatePartialCaption(sessionId, text, source = null) {
+  let partialEntry = partialEntries.get(sessionId);
+  
+  if (!text) {
+    if (partialEntry) {
+      partialEntry.remove();
+      partialEntries.delete(sessionId);
+    }
+    return;
+  }
   
-  // Map to store speaker IDs to consistent color indices
+  if (!partialEntry) {
+    partialEntry = document.createElement("div");
+    partialEntry.className = "transcript-entry partial";
+    
+    if (source) {
+      partialEntry.appendChild(createSourceLabel(source));
+    }
+    
+    const content = document.createElement("div");
+    content.className = "content";
+    partialEntry.appendChild(content);
+    partialEntries.set(sessionId, partialEntry);
+  }
+  
+  partialEntry.querySelector(".content").textContent = text;
+  
+  // Keep the partial caption as the last entry
+  transcriptEl.appendChild(partialEntry);
+  transcriptEl.scrollTop = transcriptEl.scrollHeight;
+}
+
+// "You" or "Call", for captions captured from one side of a call
+function createSourceLabel(source) {
+  const label = document.createElement("div");
+  label.className = `source-label ${source}`;
+  label.textContent = source === "me" ? "You" : "Call";
+  return label;
+}
+
+// Append diarized transcript to the UI
+function appendDiarizedTranscript(transcript, jobId, recording = null) {
+  // Give each speaker a consistent color index in order of appearance
   const speakerColorMap = new Map();
-  let nextColorIndex = 0;
+  const speakerConfidence = new Map();
+  transcript.speakers.forEach((speaker, index) => {
+    speakerColorMap.set(speaker.id, index % 8); // 8 colors available
+    speakerConfidence.set(speaker.id, speaker.confidence);
+    if (!currentSpeakers.has(speaker.id)) {
+      currentSpeakers.set(speaker.id, speaker.name);
+    }
+  });
   
-  // Process each line
-  for (const line of lines) {
-    if (line.trim() === '') continue;
+  // Process each segment
+  for (const segment of transcript.segments) {
+    if (segment.text.trim() === '') continue;
     
-    // Check if line has speaker format (Speaker: text)
-    const match = line.match(/^([^:]+):\s*(.+)$/);
-    if (match) {
-      const speakerId = match[1].trim();
-      const speakerText = match[2].trim();
-      
-      // Assign a consistent color index to this speaker
-      if (!speakerColorMap.has(speakerId)) {
-        speakerColorMap.set(speakerId, nextColorIndex % 8); // 8 colors available
-        nextColorIndex++;
-      }
-      
-      const colorIndex = speakerColorMap.get(speakerId);
-      
-      // Create a new transcript entry
-      const entry = document.createElement("div");
-      entry.className = "transcript-entry diarized";
-      entry.dataset.colorIndex = colorIndex; // Store color index as data attribute
-      
-      // Add timestamp
-      const timestamp = document.createElement("div");
-      timestamp.className = "timestamp";
-      const now = new Date();
-      timestamp.textContent = `${now.getHours().toString().padStart(2, '0')}:${now.getMinutes().toString().padStart(2, '0')}:${now.getSeconds().toString().padStart(2, '0')}`;
-      
-      // Add speaker label
-      const speaker = document.createElement("div");
-      speaker.className = "speaker-label";
-      speaker.style.color = `var(--discord-speaker${colorIndex + 1})`;
-      speaker.textContent = speakerId;
-      speaker.dataset.speakerId = speakerId;
-      
-      // Add rename button
-      const renameBtn = document.createElement("button");
-      renameBtn.className = "rename-button";
-      renameBtn.textContent = "✏️";
-      renameBtn.title = "Rename speaker";
-      renameBtn.addEventListener("click", () => openSpeakerRenameModal(speakerId));
-      
-      // Add text
-      const content = document.createElement("div");
-      content.className = "content";
-      content.textContent = speakerText;
-      
-      // Assemble entry
-      speaker.appendChild(renameBtn);
-      entry.appendChild(timestamp);
-      entry.appendChild(speaker);
-      entry.appendChild(content);
-      
-      // Add to transcript
-      transcriptEl.appendChild(entry);
-    } else {
-      // Regular transcript line
-      appendTranscript(line);
+    // Segments without a speaker are shown as regular transcript entries
+    if (!segment.speaker_id) {
+      const entry = appendTranscript(segment.text);
+      addRecordingControls(entry, recording, segment.start, segment.end);
+      continue;
     }
+    
+    const speakerId = segment.speaker_id;
+    const colorIndex = speakerColorMap.get(speakerId) ?? 0;
+    
+    // Create a new transcript entry
+    const entry = document.createElement("div");
+    entry.className = "transcript-entry diarized";
+    entry.dataset.jobId = jobId;
+    
+    // Add timestamp
+    const timestamp = document.createElement("div");
+    timestamp.className = "timestamp";
+    const now = new Date();
+    timestamp.textContent = `${now.getHours().toString().padStart(2, '0')}:${now.getMinutes().toString().padStart(2, '0')}:${now.getSeconds().toString().padStart(2, '0')}`;
+    
+    // Add speaker label
+    const speaker = document.createElement("div");
+    speaker.className = "speaker-label";
+    setSpeakerLabel(entry, speaker, speakerId, colorIndex, speakerConfidence.get(speakerId));
+    
+    // Add text
+    const content = document.createElement("div");
+    content.className = "content";
+    content.textContent = segment.text;
+    
+    // Assemble entry
+    entry.appendChild(timestamp);
+    entry.appendChild(speaker);
+    entry.appendChild(content);
+    addRecordingControls(entry, recording, segment.start, segment.end);
+    
+    // Add to transcript
+    transcriptEl.appendChild(entry);
   }
   
   // Scroll to bottom
   transcriptEl.scrollTop = transcriptEl.scrollHeight;
 }
 
+// Point a diarized entry's speaker label at a speaker
+function setSpeakerLabel(entry, label, speakerId, colorIndex, confidence) {
+  entry.dataset.colorIndex = colorIndex; // Store color index as data attribute
+  label.style.color = `var(--discord-speaker${colorIndex + 1})`;
+  label.textContent = currentSpeakers.get(speakerId) || speakerId;
+  label.dataset.speakerId = speakerId;
+  label.title = confidence != null ? `Speaker confidence: ${Math.round(confidence * 100)}%` : "";
+  
+  // Add rename button
+  const renameBtn = document.createElement("button");
+  renameBtn.className = "rename-button";
+  renameBtn.textContent = "✏️";
+  renameBtn.title = "Rename speaker";
+  renameBtn.addEventListener("click", () => openSpeakerRenameModal(speakerId, entry.dataset.jobId));
+  label.appendChild(renameBtn);
+}
+
+// Apply speaker ids from re-clustering every recording of the session together
+function relabelSpeakers(clustering) {
+  const speakers = new Map(clustering.speakers.map((speaker, index) => [speaker.id, { speaker, colorIndex: index % 8 }]));
+  clustering.speakers.forEach(speaker => {
+    if (!currentSpeakers.has(speaker.id)) {
+      currentSpeakers.set(speaker.id, speaker.name);
+    }
+  });
+  
+  // Find every label before changing any, so swapped ids aren't relabeled twice
+  const updates = clustering.relabels.map(relabel => [
+    relabel,
+    [...transcriptEl.querySelectorAll(`.transcript-entry.diarized[data-job-id="${relabel.job_id}"] .speaker-label[data-speaker-id="${relabel.from}"]`)]
+  ]);
+  
+  for (const [relabel, labels] of updates) {
+    const { speaker, colorIndex } = speakers.get(relabel.to);
+    for (const label of labels) {
+      setSpeakerLabel(label.parentElement, label, relabel.to, colorIndex, speaker.confidence);
+    }
+  }
+}
+
 // Open speaker rename modal
-function openSpeakerRenameModal(speakerId) {
-  // Set current speaker ID as data attribute
+function openSpeakerRenameModal(speakerId, jobId) {
+  // Set current speaker ID and the recording to enroll their voice from as data attributes
   speakerRenameForm.dataset.speakerId = speakerId;
+  speakerRenameForm.dataset.jobId = jobId;
   
   // Set current name as default value
   const currentName = currentSpeakers.get(speakerId) || speakerId;
@@ -523,29 +1178,24 @@ async function handleSpeakerRename(event) {
   event.preventDefault();
   
   const speakerId = speakerRenameForm.dataset.speakerId;
+  const jobId = Number(speakerRenameForm.dataset.jobId);
   const newName = speakerRenameInput.value.trim();
   
   if (!newName) return;
   
   try {
-    // Call backend to rename speaker
-    const updatedSpeaker = await invoke("rename_speaker", { speakerId, newName });
+    // Call backend to rename speaker, enrolling their voice from this recording
+    const updatedSpeaker = await invoke("rename_speaker", { speakerId, newName, jobId });
     
     // Update local map
-    currentSpeakers.set(speakerId, updatedSpeaker.name);
+    currentSpeakers.set(updatedSpeaker.id, updatedSpeaker.name);
     
-    // Update UI
-    document.querySelectorAll(`.speaker-label[data-speaker-id="${speakerId}"]`).forEach(label => {
-      label.textContent = updatedSpeaker.name;
-      
-      // Re-add the rename button
-      const renameBtn = document.createElement("button");
-      renameBtn.className = "rename-button";
-      renameBtn.textContent = "✏️";
-      renameBtn.title = "Rename speaker";
-      renameBtn.addEventListener("click", () => openSpeakerRenameModal(speakerId));
-      
-      label.appendChild(renameBtn);
+    // Update UI. Enrolled speakers are the same voice everywhere, while "SpeakerN"
+    // ids are only meaningful within one recording.
+    const scope = speakerId === updatedSpeaker.id ? "" : `[data-job-id="${jobId}"]`;
+    document.querySelectorAll(`.transcript-entry.diarized${scope} .speaker-label[data-speaker-id="${speakerId}"]`).forEach(label => {
+      const entry = label.parentElement;
+      setSpeakerLabel(entry, label, updatedSpeaker.id, Number(entry.dataset.colorIndex), undefined);
     });
     
     // Hide modal